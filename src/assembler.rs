@@ -0,0 +1,689 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fmt::Write;
+use crate::chip8::PROGRAM_START;
+use crate::disassembler::Syntax;
+
+// Assembles a program written in a subset of Octo or in Cowgod's mnemonics into a ROM
+// to be loaded at 0x200. Besides the instructions, both syntaxes accept:
+// - labels, `: name` in Octo and `name:` in Cowgod, usable anywhere an address is expected
+// - `:alias name v3` to give a register another name
+// - `:const name 12` for constants, which must be defined before being used
+// - `:byte 1 2 0x03` for data, which bare numbers also emit in Octo
+// Octo's structured statements (loop, begin/else/end, macros) are not supported.
+pub fn assemble(source: &str, syntax: Syntax) -> Result<Assembly, AssemblyError> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source, syntax),
+        position: 0,
+        syntax,
+        rom: Vec::new(),
+        labels: BTreeMap::new(),
+        aliases: HashMap::new(),
+        constants: HashMap::new(),
+        fixups: Vec::new()
+    };
+    while let Some(token) = assembler.tokens.get(assembler.position).copied() {
+        assembler.position += 1;
+        assembler.statement(token)?;
+    }
+    assembler.finish()
+}
+
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    // Address of each label
+    pub labels: BTreeMap<String, u16>
+}
+
+impl Assembly {
+    // One `0x0202 name` line per label, in address order
+    pub fn symbol_file(&self) -> String {
+        let mut labels: Vec<(&String, &u16)> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, address)| (**address, name.as_str()));
+        let mut symbols = String::new();
+        for (name, address) in labels {
+            writeln!(symbols, "{:#06x} {}", address, name).unwrap();
+        }
+        symbols
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize
+}
+
+// Splits the source into words, dropping comments. Cowgod operands are separated with
+// commas, which are dropped as well.
+fn tokenize(source: &str, syntax: Syntax) -> Vec<Token<'_>> {
+    let (comment, separators): (char, &[char]) = match syntax {
+        Syntax::Octo => ('#', &[]),
+        Syntax::Cowgod => (';', &[','])
+    };
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split(comment).next().unwrap();
+        for text in code.split(|c: char| c.is_whitespace() || separators.contains(&c)) {
+            if !text.is_empty() {
+                tokens.push(Token { text, line: index + 1 });
+            }
+        }
+    }
+    tokens
+}
+
+// A label used before it is known, patched in once all of them are
+struct Fixup {
+    offset: usize,
+    label: String,
+    line: usize,
+    // Whether the whole 16-bit word is the address, instead of the low 12 bits of an opcode
+    long: bool
+}
+
+struct Assembler<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    syntax: Syntax,
+    rom: Vec<u8>,
+    labels: BTreeMap<String, u16>,
+    aliases: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    fixups: Vec<Fixup>
+}
+
+impl<'a> Assembler<'a> {
+    fn statement(&mut self, token: Token<'a>) -> Result<(), AssemblyError> {
+        match token.text {
+            ":alias" => {
+                let name = self.identifier()?;
+                let register = self.register_operand()?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.identifier()?;
+                let value = self.next()?;
+                let value = self.value(value)?;
+                self.constants.insert(name, value);
+            }
+            ":byte" => {
+                let values = self.rest_of_line(token);
+                if values.is_empty() {
+                    return Err(error(token, "Expected a value after :byte"));
+                }
+                for value in values {
+                    let byte = self.byte(value)?;
+                    self.rom.push(byte);
+                }
+            }
+            ":" if self.syntax == Syntax::Octo => {
+                let name = self.identifier()?;
+                self.define_label(name, token)?;
+            }
+            ":call" if self.syntax == Syntax::Octo => {
+                let target = self.next()?;
+                self.emit_address(0x2000, target)?;
+            }
+            text if self.syntax == Syntax::Cowgod && text.len() > 1 && text.ends_with(':') => {
+                let name = text.trim_end_matches(':');
+                self.check_identifier(name, token)?;
+                self.define_label(name.to_string(), token)?;
+            }
+            _ => match self.syntax {
+                Syntax::Octo => self.octo_statement(token)?,
+                Syntax::Cowgod => self.cowgod_statement(token)?
+            }
+        }
+        Ok(())
+    }
+
+    fn octo_statement(&mut self, token: Token<'a>) -> Result<(), AssemblyError> {
+        if let Some(x) = self.register(token.text) {
+            return self.octo_register_statement(x, token);
+        }
+        match token.text {
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "audio" => self.emit(0xF002),
+            "scroll-down" => {
+                let n = self.nibble_operand()?;
+                self.emit(0x00C0 | n);
+            }
+            "scroll-up" => {
+                let n = self.nibble_operand()?;
+                self.emit(0x00D0 | n);
+            }
+            "plane" => {
+                let n = self.nibble_operand()?;
+                self.emit(0xF001 | n << 8);
+            }
+            "jump" => {
+                let target = self.next()?;
+                self.emit_address(0x1000, target)?;
+            }
+            "jump0" => {
+                let target = self.next()?;
+                self.emit_address(0xB000, target)?;
+            }
+            "bcd" => self.emit_x(0xF033)?,
+            "saveflags" => self.emit_x(0xF075)?,
+            "loadflags" => self.emit_x(0xF085)?,
+            "save" | "load" => {
+                let x = self.register_operand()?;
+                if self.peek().is_some_and(|next| next.text == "-") {
+                    self.position += 1;
+                    let y = self.register_operand()?;
+                    let opcode = if token.text == "save" { 0x5002 } else { 0x5003 };
+                    self.emit(opcode | (x as u16) << 8 | (y as u16) << 4);
+                } else {
+                    let opcode = if token.text == "save" { 0xF055 } else { 0xF065 };
+                    self.emit(opcode | (x as u16) << 8);
+                }
+            }
+            "sprite" => {
+                let x = self.register_operand()?;
+                let y = self.register_operand()?;
+                let n = self.nibble_operand()?;
+                self.emit(0xD000 | (x as u16) << 8 | (y as u16) << 4 | n);
+            }
+            "i" => {
+                let operator = self.next()?;
+                match operator.text {
+                    ":=" => {
+                        let source = self.next()?;
+                        match source.text {
+                            "hex" => self.emit_x(0xF029)?,
+                            "bighex" => self.emit_x(0xF030)?,
+                            "long" => {
+                                let target = self.next()?;
+                                self.emit(0xF000);
+                                self.emit_word(target)?;
+                            }
+                            _ => self.emit_address(0xA000, source)?
+                        }
+                    }
+                    "+=" => self.emit_x(0xF01E)?,
+                    _ => return Err(error(operator, "Expected := or += after i"))
+                }
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let opcode = match token.text {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A
+                };
+                self.emit_x(opcode)?;
+            }
+            "if" => {
+                let x = self.register_operand()? as u16;
+                let operator = self.next()?;
+                let opcode = match operator.text {
+                    // The instruction skips the statement after `then` when the condition is false
+                    "key" => 0xE0A1 | x << 8,
+                    "-key" => 0xE09E | x << 8,
+                    "==" | "!=" => {
+                        let operand = self.next()?;
+                        let equal = operator.text == "==";
+                        if let Some(y) = self.register(operand.text) {
+                            (if equal { 0x9000 } else { 0x5000 }) | x << 8 | (y as u16) << 4
+                        } else {
+                            let value = self.byte(operand)? as u16;
+                            (if equal { 0x4000 } else { 0x3000 }) | x << 8 | value
+                        }
+                    }
+                    _ => return Err(error(operator, &format!("Unsupported comparison {}", operator.text)))
+                };
+                self.expect("then")?;
+                self.emit(opcode);
+            }
+            // Bare numbers are data, and bare names are calls
+            _ if parse_number(token.text, self.syntax).is_some() || self.constants.contains_key(token.text) => {
+                let byte = self.byte(token)?;
+                self.rom.push(byte);
+            }
+            _ if is_identifier(token.text) => self.emit_address(0x2000, token)?,
+            _ => return Err(error(token, &format!("Unknown statement {}", token.text)))
+        }
+        Ok(())
+    }
+
+    fn octo_register_statement(&mut self, x: usize, token: Token<'a>) -> Result<(), AssemblyError> {
+        let x = x as u16;
+        let operator = self.next()?;
+        let source = self.next()?;
+        let y = self.register(source.text).map(|y| (y as u16) << 4);
+        let opcode = match (operator.text, y) {
+            (":=", _) if source.text == "random" => {
+                let mask = self.next()?;
+                0xC000 | self.byte(mask)? as u16
+            }
+            (":=", _) if source.text == "delay" => 0xF007,
+            (":=", _) if source.text == "key" => 0xF00A,
+            (":=", Some(y)) => 0x8000 | y,
+            (":=", None) => 0x6000 | self.byte(source)? as u16,
+            ("+=", Some(y)) => 0x8004 | y,
+            ("+=", None) => 0x7000 | self.byte(source)? as u16,
+            ("-=", Some(y)) => 0x8005 | y,
+            // Subtracting a constant is adding its two's complement
+            ("-=", None) => 0x7000 | (self.byte(source)? as u16).wrapping_neg() & 0xFF,
+            ("|=", Some(y)) => 0x8001 | y,
+            ("&=", Some(y)) => 0x8002 | y,
+            ("^=", Some(y)) => 0x8003 | y,
+            (">>=", Some(y)) => 0x8006 | y,
+            ("=-", Some(y)) => 0x8007 | y,
+            ("<<=", Some(y)) => 0x800E | y,
+            _ => return Err(error(operator, &format!("Unsupported operation {} {} {}", token.text, operator.text, source.text)))
+        };
+        self.emit(opcode | x << 8);
+        Ok(())
+    }
+
+    fn cowgod_statement(&mut self, token: Token<'a>) -> Result<(), AssemblyError> {
+        match token.text.to_ascii_uppercase().as_str() {
+            "CLS" => self.emit(0x00E0),
+            "RET" => self.emit(0x00EE),
+            "SCR" => self.emit(0x00FB),
+            "SCL" => self.emit(0x00FC),
+            "EXIT" => self.emit(0x00FD),
+            "LOW" => self.emit(0x00FE),
+            "HIGH" => self.emit(0x00FF),
+            "AUDIO" => self.emit(0xF002),
+            "SCD" => {
+                let n = self.nibble_operand()?;
+                self.emit(0x00C0 | n);
+            }
+            "SCU" => {
+                let n = self.nibble_operand()?;
+                self.emit(0x00D0 | n);
+            }
+            "PLANE" => {
+                let n = self.nibble_operand()?;
+                self.emit(0xF001 | n << 8);
+            }
+            "SYS" => {
+                let target = self.next()?;
+                self.emit_address(0x0000, target)?;
+            }
+            "JP" => {
+                let target = self.next()?;
+                match self.register(target.text) {
+                    Some(0) => {
+                        let target = self.next()?;
+                        self.emit_address(0xB000, target)?;
+                    }
+                    // BXNN, as listed for the variants with the jumping quirk
+                    Some(x) => {
+                        let offset = self.next()?;
+                        let offset = self.byte(offset)?;
+                        self.emit(0xB000 | (x as u16) << 8 | offset as u16);
+                    }
+                    None => self.emit_address(0x1000, target)?
+                }
+            }
+            "CALL" => {
+                let target = self.next()?;
+                self.emit_address(0x2000, target)?;
+            }
+            "SE" | "SNE" => {
+                let x = self.register_operand()? as u16;
+                let operand = self.next()?;
+                let equal = token.text.eq_ignore_ascii_case("SE");
+                let opcode = if let Some(y) = self.register(operand.text) {
+                    (if equal { 0x5000 } else { 0x9000 }) | (y as u16) << 4
+                } else {
+                    (if equal { 0x3000 } else { 0x4000 }) | self.byte(operand)? as u16
+                };
+                self.emit(opcode | x << 8);
+            }
+            "LD" => self.cowgod_load()?,
+            "ADD" => {
+                let destination = self.next()?;
+                if destination.text.eq_ignore_ascii_case("I") {
+                    self.emit_x(0xF01E)?;
+                } else {
+                    let x = self.register_token(destination)? as u16;
+                    let operand = self.next()?;
+                    let opcode = match self.register(operand.text) {
+                        Some(y) => 0x8004 | (y as u16) << 4,
+                        None => 0x7000 | self.byte(operand)? as u16
+                    };
+                    self.emit(opcode | x << 8);
+                }
+            }
+            "OR" => self.emit_xy(0x8001)?,
+            "AND" => self.emit_xy(0x8002)?,
+            "XOR" => self.emit_xy(0x8003)?,
+            "SUB" => self.emit_xy(0x8005)?,
+            "SUBN" => self.emit_xy(0x8007)?,
+            "SHR" | "SHL" => {
+                let x = self.register_operand()?;
+                // VY is optional, shifting VX in place when left out
+                let y = match self.peek() {
+                    Some(next) if next.line == token.line && self.register(next.text).is_some() => {
+                        self.register_operand()?
+                    }
+                    _ => x
+                };
+                let opcode = if token.text.eq_ignore_ascii_case("SHR") { 0x8006 } else { 0x800E };
+                self.emit(opcode | (x as u16) << 8 | (y as u16) << 4);
+            }
+            "RND" => {
+                let x = self.register_operand()? as u16;
+                let mask = self.next()?;
+                let mask = self.byte(mask)? as u16;
+                self.emit(0xC000 | x << 8 | mask);
+            }
+            "DRW" => {
+                let x = self.register_operand()? as u16;
+                let y = self.register_operand()? as u16;
+                let n = self.nibble_operand()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n);
+            }
+            "SKP" => self.emit_x(0xE09E)?,
+            "SKNP" => self.emit_x(0xE0A1)?,
+            "PITCH" => self.emit_x(0xF03A)?,
+            "SAVE" | "LOAD" => {
+                let x = self.register_operand()? as u16;
+                self.expect("-")?;
+                let y = self.register_operand()? as u16;
+                let opcode = if token.text.eq_ignore_ascii_case("SAVE") { 0x5002 } else { 0x5003 };
+                self.emit(opcode | x << 8 | y << 4);
+            }
+            "DB" | "DW" => {
+                let values = self.rest_of_line(token);
+                if values.is_empty() {
+                    return Err(error(token, &format!("Expected a value after {}", token.text)));
+                }
+                for value in values {
+                    if token.text.eq_ignore_ascii_case("DB") {
+                        let byte = self.byte(value)?;
+                        self.rom.push(byte);
+                    } else {
+                        self.emit_word(value)?;
+                    }
+                }
+            }
+            _ => return Err(error(token, &format!("Unknown instruction {}", token.text)))
+        }
+        Ok(())
+    }
+
+    fn cowgod_load(&mut self) -> Result<(), AssemblyError> {
+        let destination = self.next()?;
+        let opcode = match destination.text.to_ascii_uppercase().as_str() {
+            "I" => {
+                let source = self.next()?;
+                // LONG is the XO-CHIP F000 NNNN, which takes a 16-bit address
+                if source.text.eq_ignore_ascii_case("LONG") {
+                    let target = self.next()?;
+                    self.emit(0xF000);
+                    return self.emit_word(target);
+                }
+                return self.emit_address(0xA000, source);
+            }
+            "DT" => 0xF015,
+            "ST" => 0xF018,
+            "F" => 0xF029,
+            "HF" => 0xF030,
+            "B" => 0xF033,
+            "[I]" => 0xF055,
+            "R" => 0xF075,
+            _ => {
+                let x = self.register_token(destination)? as u16;
+                let source = self.next()?;
+                let opcode = match source.text.to_ascii_uppercase().as_str() {
+                    "DT" => 0xF007,
+                    "K" => 0xF00A,
+                    "[I]" => 0xF065,
+                    "R" => 0xF085,
+                    _ => match self.register(source.text) {
+                        Some(y) => 0x8000 | (y as u16) << 4,
+                        None => 0x6000 | self.byte(source)? as u16
+                    }
+                };
+                self.emit(opcode | x << 8);
+                return Ok(());
+            }
+        };
+        self.emit_x(opcode)
+    }
+
+    fn finish(mut self) -> Result<Assembly, AssemblyError> {
+        if PROGRAM_START + self.rom.len() > 0x10000 {
+            return Err(AssemblyError {
+                line: self.tokens.last().map_or(0, |token| token.line),
+                message: format!("The program is {} bytes long, which doesn't fit in memory", self.rom.len())
+            });
+        }
+        for fixup in &self.fixups {
+            let Some(&address) = self.labels.get(&fixup.label) else {
+                return Err(AssemblyError { line: fixup.line, message: format!("Unknown label {}", fixup.label) });
+            };
+            if fixup.long {
+                self.rom[fixup.offset] = (address >> 8) as u8;
+                self.rom[fixup.offset + 1] = address as u8;
+            } else {
+                if address > 0xFFF {
+                    return Err(AssemblyError {
+                        line: fixup.line,
+                        message: format!("Label {} at {:#06x} is out of reach of a 12-bit address", fixup.label, address)
+                    });
+                }
+                self.rom[fixup.offset] |= (address >> 8) as u8;
+                self.rom[fixup.offset + 1] = address as u8;
+            }
+        }
+        Ok(Assembly { rom: self.rom, labels: self.labels })
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, AssemblyError> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(*token)
+            }
+            None => Err(AssemblyError {
+                line: self.tokens.last().map_or(0, |token| token.line),
+                message: "Unexpected end of file".to_string()
+            })
+        }
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AssemblyError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(error(token, &format!("Expected {}, found {}", text, token.text)));
+        }
+        Ok(())
+    }
+
+    // The tokens left on the line of `token`
+    fn rest_of_line(&mut self, token: Token<'a>) -> Vec<Token<'a>> {
+        let mut tokens = Vec::new();
+        while let Some(next) = self.peek().filter(|next| next.line == token.line) {
+            tokens.push(next);
+            self.position += 1;
+        }
+        tokens
+    }
+
+    fn identifier(&mut self) -> Result<String, AssemblyError> {
+        let token = self.next()?;
+        self.check_identifier(token.text, token)?;
+        Ok(token.text.to_string())
+    }
+
+    fn check_identifier(&self, name: &str, token: Token) -> Result<(), AssemblyError> {
+        if !is_identifier(name) || self.register(name).is_some() {
+            return Err(error(token, &format!("Invalid name {}", name)));
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: String, token: Token) -> Result<(), AssemblyError> {
+        let address = (PROGRAM_START + self.rom.len()) as u16;
+        if self.labels.insert(name.clone(), address).is_some() {
+            return Err(error(token, &format!("Label {} is defined twice", name)));
+        }
+        Ok(())
+    }
+
+    // V0 to VF, in either case, or an alias
+    fn register(&self, text: &str) -> Option<usize> {
+        if let Some(register) = self.aliases.get(text) {
+            return Some(*register);
+        }
+        let index = text.strip_prefix(['v', 'V'])?;
+        if index.len() != 1 {
+            return None;
+        }
+        usize::from_str_radix(index, 16).ok()
+    }
+
+    fn register_token(&self, token: Token) -> Result<usize, AssemblyError> {
+        self.register(token.text)
+            .ok_or_else(|| error(token, &format!("Expected a register, found {}", token.text)))
+    }
+
+    fn register_operand(&mut self) -> Result<usize, AssemblyError> {
+        let token = self.next()?;
+        self.register_token(token)
+    }
+
+    fn value(&self, token: Token) -> Result<i64, AssemblyError> {
+        if let Some(value) = self.constants.get(token.text) {
+            return Ok(*value);
+        }
+        parse_number(token.text, self.syntax)
+            .ok_or_else(|| error(token, &format!("Expected a number, found {}", token.text)))
+    }
+
+    // Negative bytes are stored as their two's complement
+    fn byte(&self, token: Token) -> Result<u8, AssemblyError> {
+        let value = self.value(token)?;
+        if !(-128..=255).contains(&value) {
+            return Err(error(token, &format!("{} doesn't fit in a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble_operand(&mut self) -> Result<u16, AssemblyError> {
+        let token = self.next()?;
+        let value = self.value(token)?;
+        if !(0..=15).contains(&value) {
+            return Err(error(token, &format!("{} doesn't fit in 4 bits", value)));
+        }
+        Ok(value as u16)
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.rom.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    fn emit_x(&mut self, opcode: u16) -> Result<(), AssemblyError> {
+        let x = self.register_operand()? as u16;
+        self.emit(opcode | x << 8);
+        Ok(())
+    }
+
+    fn emit_xy(&mut self, opcode: u16) -> Result<(), AssemblyError> {
+        let x = self.register_operand()? as u16;
+        let y = self.register_operand()? as u16;
+        self.emit(opcode | x << 8 | y << 4);
+        Ok(())
+    }
+
+    // Emits an opcode taking a 12-bit address, given as a number, a constant or a label
+    fn emit_address(&mut self, opcode: u16, target: Token) -> Result<(), AssemblyError> {
+        if !self.is_label(target.text) {
+            let address = self.value(target)?;
+            if !(0..=0xFFF).contains(&address) {
+                return Err(error(target, &format!("{:#x} doesn't fit in a 12-bit address", address)));
+            }
+            self.emit(opcode | address as u16);
+            return Ok(());
+        }
+        self.fixups.push(Fixup { offset: self.rom.len(), label: target.text.to_string(), line: target.line, long: false });
+        self.emit(opcode);
+        Ok(())
+    }
+
+    // Emits a 16-bit word, given as a number, a constant or a label. It is the address
+    // following XO-CHIP's F000 prefix, or data for Cowgod's DW.
+    fn emit_word(&mut self, target: Token) -> Result<(), AssemblyError> {
+        if self.is_label(target.text) {
+            self.fixups.push(Fixup { offset: self.rom.len(), label: target.text.to_string(), line: target.line, long: true });
+            self.emit(0);
+            return Ok(());
+        }
+        let address = self.value(target)?;
+        if !(0..=0xFFFF).contains(&address) {
+            return Err(error(target, &format!("{:#x} doesn't fit in 16 bits", address)));
+        }
+        self.emit(address as u16);
+        Ok(())
+    }
+
+    fn is_label(&self, text: &str) -> bool {
+        is_identifier(text) && !self.constants.contains_key(text) && parse_number(text, self.syntax).is_none()
+    }
+}
+
+fn error(token: Token, message: &str) -> AssemblyError {
+    AssemblyError { line: token.line, message: message.to_string() }
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Decimal, 0x hexadecimal or 0b binary numbers, optionally negative. Cowgod sources
+// also use # for hexadecimal and $ for binary.
+fn parse_number(text: &str, syntax: Syntax) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text)
+    };
+    let (radix, digits) = if let Some(hex) = digits.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        (2, binary)
+    } else if let (Syntax::Cowgod, Some(hex)) = (syntax, digits.strip_prefix('#')) {
+        (16, hex)
+    } else if let (Syntax::Cowgod, Some(binary)) = (syntax, digits.strip_prefix('$')) {
+        (2, binary)
+    } else {
+        (10, digits)
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let value = i64::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
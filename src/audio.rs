@@ -0,0 +1,251 @@
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "audio")]
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "audio")]
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+#[cfg(feature = "audio")]
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use fundsp::hacker32::{afollow, dcblock, saw_hz, sine_hz, square_hz, triangle_hz, AudioUnit};
+
+// Pitch of the buzzer. Every interpreter had its own, the programs don't get to choose it.
+const BEEP_FREQUENCY: f32 = 440.0;
+// Times for the tone to fade in and out: short enough to sound like a plain beep,
+// long enough to avoid the clicks of a wave cut in the middle
+const ATTACK_TIME: f32 = 0.002;
+const RELEASE_TIME: f32 = 0.005;
+// Length in bytes of the XO-CHIP audio pattern loaded by F002, played one bit at a time
+pub const PATTERN_LENGTH: usize = 16;
+const PATTERN_BITS: f64 = (PATTERN_LENGTH * 8) as f64;
+// Bits played per second at the default pitch of 64
+const PATTERN_RATE: f64 = 4000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine
+}
+
+// Names used to pick a waveform on the command line
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Waveform, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("Unknown waveform {}, expected square, triangle, sawtooth or sine", name))
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Waveform::Square => "square",
+            Waveform::Triangle => "triangle",
+            Waveform::Sawtooth => "sawtooth",
+            Waveform::Sine => "sine"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// How the buzzer sounds. The volume goes from 0 (muted) to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioSettings {
+    pub waveform: Waveform,
+    pub volume: f32
+}
+
+impl Default for AudioSettings {
+    fn default() -> AudioSettings {
+        AudioSettings {
+            waveform: Waveform::Square,
+            volume: 0.25
+        }
+    }
+}
+
+// What the machine plays, see `Chip8::sound`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sound {
+    // Whether the sound timer is running
+    pub active: bool,
+    // Once an XO-CHIP program loaded a pattern with F002, it is played instead of the beep
+    pub pattern: Option<[u8; PATTERN_LENGTH]>,
+    // The playback rate of the pattern set with FX3A: 4000 * 2^((pitch - 64) / 48) bits per second
+    pub pitch: u8
+}
+
+impl Default for Sound {
+    fn default() -> Sound {
+        Sound {
+            active: false,
+            pattern: None,
+            pitch: 64
+        }
+    }
+}
+
+// Generates the sound of the machine one sample at a time, without depending on an
+// audio device. The tone keeps running while the buzzer is off, only its envelope
+// goes up and down, so that it always starts and stops smoothly.
+pub struct Synth {
+    oscillator: Box<dyn AudioUnit>,
+    gain: f32,
+    envelope: Box<dyn AudioUnit>,
+    // A pattern of only zeros or ones would otherwise be a constant offset
+    dc_blocker: Box<dyn AudioUnit>,
+    volume: f32,
+    sample_rate: u32,
+    sound: Sound,
+    // Position in the pattern, in bits
+    pattern_position: f64
+}
+
+impl Synth {
+    pub fn new(settings: &AudioSettings, sample_rate: u32) -> Synth {
+        // The band-limited wavetables of fundsp peak at about a third, the gains bring
+        // every waveform to a peak of about 1 like the sine
+        let (mut oscillator, gain): (Box<dyn AudioUnit>, f32) = match settings.waveform {
+            Waveform::Square => (Box::new(square_hz(BEEP_FREQUENCY)), 3.0),
+            Waveform::Triangle => (Box::new(triangle_hz(BEEP_FREQUENCY)), 2.7),
+            Waveform::Sawtooth => (Box::new(saw_hz(BEEP_FREQUENCY)), 2.6),
+            Waveform::Sine => (Box::new(sine_hz(BEEP_FREQUENCY)), 1.0)
+        };
+        let mut envelope: Box<dyn AudioUnit> = Box::new(afollow(ATTACK_TIME, RELEASE_TIME));
+        let mut dc_blocker: Box<dyn AudioUnit> = Box::new(dcblock());
+        oscillator.set_sample_rate(sample_rate as f64);
+        envelope.set_sample_rate(sample_rate as f64);
+        dc_blocker.set_sample_rate(sample_rate as f64);
+        Synth {
+            oscillator,
+            gain,
+            envelope,
+            dc_blocker,
+            volume: settings.volume.clamp(0.0, 1.0),
+            sample_rate,
+            sound: Sound::default(),
+            pattern_position: 0.0
+        }
+    }
+
+    // Changes what is played, usually with `Chip8::sound` once per frame
+    pub fn play(&mut self, sound: &Sound) {
+        self.sound = *sound;
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let level = self.envelope.filter_mono(if self.sound.active { 1.0 } else { 0.0 });
+        let wave = match self.sound.pattern {
+            Some(pattern) => self.next_pattern_sample(&pattern),
+            None => self.oscillator.get_mono() * self.gain
+        };
+        wave * level * self.volume
+    }
+
+    // Resamples the pattern to the output rate. Each sample is the average of the bits
+    // it covers, which keeps the high pitches, up to about 64000 bits per second, from
+    // aliasing.
+    fn next_pattern_sample(&mut self, pattern: &[u8; PATTERN_LENGTH]) -> f32 {
+        let rate = PATTERN_RATE * 2f64.powf((self.sound.pitch as f64 - 64.0) / 48.0);
+        let step = rate / self.sample_rate as f64;
+        let end = self.pattern_position + step;
+        let mut position = self.pattern_position;
+        let mut high = 0.0;
+        while position < end {
+            let bit = position.floor();
+            let next = (bit + 1.0).min(end);
+            if pattern_bit(pattern, bit as usize % (PATTERN_LENGTH * 8)) {
+                high += next - position;
+            }
+            position = next;
+        }
+        self.pattern_position = end % PATTERN_BITS;
+        let level = (high / step) as f32 * 2.0 - 1.0;
+        self.dc_blocker.filter_mono(level)
+    }
+}
+
+// The bits of the pattern are played from the most significant bit of the first byte
+fn pattern_bit(pattern: &[u8; PATTERN_LENGTH], bit: usize) -> bool {
+    pattern[bit / 8] >> (7 - bit % 8) & 1 == 1
+}
+
+// Plays the sound of the machine on the default output device. Without a device, or when it can't
+// be opened, the null backend plays nothing instead, so the emulator runs the same with
+// or without sound. Only built with the audio feature, which brings in cpal.
+#[cfg(feature = "audio")]
+pub struct AudioOutput {
+    sound: Arc<RwLock<Sound>>,
+    // None for the null backend. Dropping the stream stops the sound.
+    _stream: Option<Stream>
+}
+
+#[cfg(feature = "audio")]
+impl AudioOutput {
+    // Fails when there is no output device or it can't be opened, see `null`
+    pub fn open(settings: &AudioSettings) -> Result<AudioOutput, String> {
+        let sound = Arc::new(RwLock::new(Sound::default()));
+        let stream = open_stream(settings, sound.clone())?;
+        Ok(AudioOutput { sound, _stream: Some(stream) })
+    }
+
+    pub fn null() -> AudioOutput {
+        AudioOutput {
+            sound: Arc::new(RwLock::new(Sound::default())),
+            _stream: None
+        }
+    }
+
+    pub fn play(&self, sound: Sound) {
+        *self.sound.write().unwrap() = sound;
+    }
+}
+
+#[cfg(feature = "audio")]
+fn open_stream(settings: &AudioSettings, sound: Arc<RwLock<Sound>>) -> Result<Stream, String> {
+    let device = cpal::default_host().default_output_device().ok_or("no output device")?;
+    let config = device.default_output_config().map_err(|error| error.to_string())?;
+    let sample_format = config.sample_format();
+    let config = config.config();
+    match sample_format {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, settings, sound),
+        SampleFormat::F64 => build_stream::<f64>(&device, &config, settings, sound),
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, settings, sound),
+        SampleFormat::I32 => build_stream::<i32>(&device, &config, settings, sound),
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, settings, sound),
+        SampleFormat::U8 => build_stream::<u8>(&device, &config, settings, sound),
+        format => Err(format!("unsupported sample format {}", format))
+    }
+}
+
+#[cfg(feature = "audio")]
+fn build_stream<T>(device: &Device, config: &StreamConfig, settings: &AudioSettings, sound: Arc<RwLock<Sound>>) -> Result<Stream, String>
+where
+    T: SizedSample + FromSample<f32>
+{
+    let channels = config.channels as usize;
+    let mut synth = Synth::new(settings, config.sample_rate.0);
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            // The audio thread never waits for the emulator, it keeps the previous state instead
+            if let Ok(sound) = sound.try_read() {
+                synth.play(&sound);
+            }
+            for frame in data.chunks_mut(channels) {
+                frame.fill(T::from_sample(synth.next_sample()));
+            }
+        },
+        |error| eprintln!("Audio error: {}", error),
+        None
+    ).map_err(|error| error.to_string())?;
+    stream.play().map_err(|error| error.to_string())?;
+    Ok(stream)
+}
@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use rust_chip8_emulator::assembler::assemble;
+use rust_chip8_emulator::disassembler::Syntax;
+
+const USAGE: &str = "Usage: chip8-asm <source> [-o <program>] [--syntax octo|cowgod]";
+
+// Assembles a program into a .ch8 file next to the source, unless another path is given
+// with -o, and writes the address of its labels to a .sym file alongside it.
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut source_path = None;
+    let mut output_path = None;
+    let mut syntax = Syntax::Octo;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output_path = Some(PathBuf::from(args.next().ok_or(USAGE)?)),
+            "--syntax" => syntax = args.next().ok_or(USAGE)?.parse()?,
+            _ if source_path.is_none() && !arg.starts_with('-') => source_path = Some(PathBuf::from(arg)),
+            _ => return Err(USAGE.to_string())
+        }
+    }
+    let source_path = source_path.ok_or(USAGE)?;
+    let output_path = output_path.unwrap_or_else(|| source_path.with_extension("ch8"));
+    let source = fs::read_to_string(&source_path)
+        .map_err(|error| format!("Can't read {}: {}", source_path.display(), error))?;
+    let assembly = assemble(&source, syntax)
+        .map_err(|error| format!("{}: {}", source_path.display(), error))?;
+    fs::write(&output_path, &assembly.rom)
+        .map_err(|error| format!("Can't write {}: {}", output_path.display(), error))?;
+    let symbol_path = output_path.with_extension("sym");
+    fs::write(&symbol_path, assembly.symbol_file())
+        .map_err(|error| format!("Can't write {}: {}", symbol_path.display(), error))?;
+    println!("Wrote {} bytes to {}", assembly.rom.len(), output_path.display());
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+use rust_chip8_emulator::disassembler::{disassemble, Syntax};
+use rust_chip8_emulator::variant::Variant;
+
+const USAGE: &str = "Usage: chip8-disasm <program> [--variant chip8|schip|schip-extended|xochip] [--syntax octo|cowgod]";
+
+// Prints an annotated listing of a CHIP-8 program.
+// The variant defaults to XO-CHIP for .xo8 files and to CHIP-8 for the others.
+fn main() -> ExitCode {
+    match run() {
+        Ok(listing) => {
+            print!("{}", listing);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<String, String> {
+    let mut path = None;
+    let mut variant = None;
+    let mut syntax = Syntax::Octo;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--variant" => variant = Some(args.next().ok_or(USAGE)?.parse()?),
+            "--syntax" => syntax = args.next().ok_or(USAGE)?.parse()?,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(USAGE.to_string())
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+    let rom = fs::read(&path).map_err(|error| format!("Can't read {}: {}", path, error))?;
+    let variant = variant.unwrap_or_else(|| {
+        if Path::new(&path).extension().is_some_and(|extension| extension == "xo8") {
+            Variant::XoChip
+        } else {
+            Variant::CosmacVip
+        }
+    });
+    Ok(disassemble(&rom, variant, syntax))
+}
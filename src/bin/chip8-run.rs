@@ -0,0 +1,164 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+use rust_chip8_emulator::audio::{AudioSettings, Waveform};
+use rust_chip8_emulator::chip8::Chip8;
+use rust_chip8_emulator::dump::{framebuffer_to_ascii, framebuffer_to_png, registers_to_json};
+use rust_chip8_emulator::quirks::Quirks;
+use rust_chip8_emulator::variant::{detect_variant, Variant};
+use rust_chip8_emulator::wav::WavRecorder;
+
+const USAGE: &str = "Usage: chip8-run <program> [--variant chip8|schip|schip-extended|xochip] [--seed <number>]
+                 [--frames <count> | --cycles <count>] [--press <key>@<start>[-<end>]]...
+                 [--ascii <file>|-] [--png <file>] [--json <file>|-]
+                 [--wav <file>] [--waveform square|triangle|sawtooth|sine] [--volume <percent>]";
+
+// A key held from the start frame up to, but not including, the end frame
+struct KeyPress {
+    key: usize,
+    start: u64,
+    end: Option<u64>
+}
+
+// Runs a program without a window for a number of frames (60 by default) or
+// instructions, then dumps the framebuffer and the registers. With --cycles, --press
+// counts instructions instead of frames.
+// Without any dump option the framebuffer is printed as ASCII. --wav records the
+// sound of the frames that were run; with --cycles, of the frames that were completed.
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+// Returns whether the program ran without errors
+fn run() -> Result<bool, String> {
+    let mut path = None;
+    let mut variant: Option<Variant> = None;
+    let mut seed = 0;
+    let mut count = 60;
+    let mut by_cycle = false;
+    let mut presses = Vec::new();
+    let mut ascii_path = None;
+    let mut png_path = None;
+    let mut json_path = None;
+    let mut wav_path = None;
+    let mut audio_settings = AudioSettings::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--variant" => variant = Some(args.next().ok_or(USAGE)?.parse()?),
+            "--seed" => seed = parse_number(&args.next().ok_or(USAGE)?)?,
+            "--frames" | "--cycles" => {
+                count = parse_number(&args.next().ok_or(USAGE)?)?;
+                by_cycle = arg == "--cycles";
+            }
+            "--press" => presses.push(parse_press(&args.next().ok_or(USAGE)?)?),
+            "--ascii" => ascii_path = Some(args.next().ok_or(USAGE)?),
+            "--png" => png_path = Some(args.next().ok_or(USAGE)?),
+            "--json" => json_path = Some(args.next().ok_or(USAGE)?),
+            "--wav" => wav_path = Some(args.next().ok_or(USAGE)?),
+            "--waveform" => audio_settings.waveform = args.next().ok_or(USAGE)?.parse::<Waveform>()?,
+            "--volume" => {
+                let volume = parse_number(&args.next().ok_or(USAGE)?)?.min(100);
+                audio_settings.volume = volume as f32 / 100.0;
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(USAGE.to_string())
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+    let rom = fs::read(&path).map_err(|error| format!("Can't read {}: {}", path, error))?;
+    let variant = variant.unwrap_or_else(|| detect_variant(Path::new(&path), &rom));
+    let mut quirks = Quirks::for_variant(&variant);
+    for warning in quirks.load_overrides(Path::new(&path)) {
+        eprintln!("{}", warning);
+    }
+
+    let mut chip8 = Chip8::new(variant, quirks, seed);
+    chip8.load_rom(&rom).map_err(|error| error.to_string())?;
+    let wav_error = |error: std::io::Error| format!("Can't write {}: {}", wav_path.as_deref().unwrap_or_default(), error);
+    let mut recorder = match &wav_path {
+        Some(wav_path) => Some(WavRecorder::create(Path::new(wav_path), &audio_settings).map_err(wav_error)?),
+        None => None
+    };
+    let mut succeeded = true;
+    for tick in 0..count {
+        let mut keys = [false; 16];
+        for press in &presses {
+            if press.start <= tick && press.end.is_none_or(|end| tick < end) {
+                keys[press.key] = true;
+            }
+        }
+        for (key, pressed) in keys.into_iter().enumerate() {
+            chip8.set_key(key, pressed);
+        }
+        let result = if by_cycle { chip8.step() } else { chip8.run_frame() };
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            succeeded = false;
+            break;
+        }
+        // The timers tick at the end of every frame, which is what the recording follows
+        if let Some(recorder) = recorder.as_mut().filter(|_| chip8.at_frame_start()) {
+            recorder.record_frame(&chip8.sound()).map_err(wav_error)?;
+        }
+        if chip8.has_exited() {
+            break;
+        }
+    }
+    if let Some(recorder) = recorder {
+        recorder.finish().map_err(wav_error)?;
+    }
+
+    if ascii_path.is_none() && png_path.is_none() && json_path.is_none() {
+        ascii_path = Some("-".to_string());
+    }
+    if let Some(ascii_path) = ascii_path {
+        write_output(&ascii_path, framebuffer_to_ascii(chip8.framebuffer()).as_bytes())?;
+    }
+    if let Some(png_path) = png_path {
+        write_output(&png_path, &framebuffer_to_png(chip8.framebuffer()))?;
+    }
+    if let Some(json_path) = json_path {
+        write_output(&json_path, registers_to_json(&chip8).as_bytes())?;
+    }
+    Ok(succeeded)
+}
+
+// Decimal, or hexadecimal with a 0x prefix
+fn parse_number(value: &str) -> Result<u64, String> {
+    let number = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse()
+    };
+    number.map_err(|_| format!("Invalid number: {}", value))
+}
+
+// `5@10-20` holds key 5 from frame 10 to frame 20, `5@10` until the end of the run
+fn parse_press(value: &str) -> Result<KeyPress, String> {
+    let invalid = || format!("Invalid key press {}, expected <key>@<start>[-<end>]", value);
+    let (key, frames) = value.split_once('@').ok_or_else(invalid)?;
+    let key = usize::from_str_radix(key, 16).ok().filter(|key| *key < 16).ok_or_else(invalid)?;
+    let (start, end) = match frames.split_once('-') {
+        Some((start, end)) => (parse_number(start)?, Some(parse_number(end)?)),
+        None => (parse_number(frames)?, None)
+    };
+    Ok(KeyPress { key, start, end })
+}
+
+// Writes to a file, or to the standard output for `-`
+fn write_output(path: &str, contents: &[u8]) -> Result<(), String> {
+    let result = if path == "-" {
+        std::io::stdout().write_all(contents)
+    } else {
+        fs::write(path, contents)
+    };
+    result.map_err(|error| format!("Can't write {}: {}", path, error))
+}
@@ -0,0 +1,812 @@
+use std::fmt;
+use crate::audio::{Sound, PATTERN_LENGTH};
+use crate::display::{Display, PLANE_COUNT};
+use crate::error::EmulatorError;
+use crate::font::{BIG_SPRITES, BIG_SPRITES_ADDRESS, SPRITES};
+use crate::instruction::{decode, Instruction};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::rpl::RPL_FLAG_COUNT;
+use crate::savestate::{hash_rom, SaveStateError, StateReader, StateWriter};
+use crate::variant::Variant;
+
+// Programs are loaded at this address, which is where their execution starts
+pub const PROGRAM_START: usize = 0x200;
+
+// State of an FX0A instruction waiting for a key. Like on the VIP, the instruction
+// completes when the key is released rather than when it is pressed.
+struct KeyWait {
+    register: usize,
+    pressed_key: Option<usize>
+}
+
+// The emulated machine, without any threads or locks: the caller drives it with
+// `step` or `run_frame`, feeds it the keys and reads the framebuffer back.
+pub struct Chip8 {
+    registers: [u8; 16],
+    i_register: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    stack: Vec<u16>,
+    program_counter: u16,
+    stack_pointer: u8,
+    memory: Vec<u8>,
+    display: Display,
+    keys: [bool; 16],
+    instructions_per_frame: u32,
+    // Cycles run since the timers last ticked, they tick every instructions_per_frame cycles
+    frame_cycles: u32,
+    variant: Variant,
+    quirks: Quirks,
+    rng: Rng,
+    key_wait: Option<KeyWait>,
+    // Set by a draw with the display wait quirk, `step` then skips to the next frame
+    waiting_for_display: bool,
+    exited: bool,
+    rpl_flags: [u8; RPL_FLAG_COUNT],
+    // Set by FX75 until the caller takes the flags to save them, see `take_dirty_flags`
+    rpl_flags_dirty: bool,
+    // Hash of the whole program, which save states are checked against
+    rom_hash: u64,
+    // XO-CHIP audio state, set with F002 and FX3A
+    audio_pattern: Option<[u8; PATTERN_LENGTH]>,
+    pitch: u8
+}
+
+impl Chip8 {
+    pub fn new(variant: Variant, quirks: Quirks, seed: u64) -> Chip8 {
+        let mut chip8 = Chip8 {
+            registers: [0; 16],
+            i_register: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            stack: vec![0u16; 16],
+            stack_pointer: 0,
+            program_counter: PROGRAM_START as u16,
+            memory: Vec::new(),
+            display: Display::new(),
+            keys: [false; 16],
+            instructions_per_frame: 10,
+            frame_cycles: 0,
+            variant,
+            quirks,
+            rng: Rng::new(seed),
+            key_wait: None,
+            waiting_for_display: false,
+            exited: false,
+            rpl_flags: [0; RPL_FLAG_COUNT],
+            rpl_flags_dirty: false,
+            rom_hash: hash_rom(&[]),
+            audio_pattern: None,
+            pitch: 64
+        };
+        chip8.reset();
+        chip8
+    }
+
+    // Resets the machine and loads a program at 0x200
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), RomTooLarge> {
+        let capacity = self.variant.memory_size() - PROGRAM_START;
+        if rom.len() > capacity {
+            return Err(RomTooLarge { size: rom.len(), capacity });
+        }
+        self.reset();
+        self.memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        self.rom_hash = hash_rom(rom);
+        Ok(())
+    }
+
+    // Everything but the keys, the random number generator and the RPL flags goes back
+    // to its power-on state
+    fn reset(&mut self) {
+        self.registers = [0; 16];
+        self.i_register = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.frame_cycles = 0;
+        self.stack = vec![0u16; 16];
+        self.stack_pointer = 0;
+        self.program_counter = PROGRAM_START as u16;
+        self.memory = vec![0; self.variant.memory_size()];
+        self.memory[..SPRITES.len()].copy_from_slice(&SPRITES);
+        self.memory[BIG_SPRITES_ADDRESS..BIG_SPRITES_ADDRESS + BIG_SPRITES.len()].copy_from_slice(&BIG_SPRITES);
+        self.display = Display::new();
+        self.key_wait = None;
+        self.waiting_for_display = false;
+        self.exited = false;
+        self.audio_pattern = None;
+        self.pitch = 64;
+    }
+
+    // Restores RPL flags saved by an earlier run. The machine never touches the disk
+    // itself: the caller loads the flags with this and saves them with `take_dirty_flags`.
+    pub fn set_rpl_flags(&mut self, flags: [u8; RPL_FLAG_COUNT]) {
+        self.rpl_flags = flags;
+        self.rpl_flags_dirty = false;
+    }
+
+    pub fn rpl_flags(&self) -> &[u8; RPL_FLAG_COUNT] {
+        &self.rpl_flags
+    }
+
+    // The flags if FX75 changed them since the last call, for the caller to save
+    pub fn take_dirty_flags(&mut self) -> Option<[u8; RPL_FLAG_COUNT]> {
+        if !self.rpl_flags_dirty {
+            return None;
+        }
+        self.rpl_flags_dirty = false;
+        Some(self.rpl_flags)
+    }
+
+    // The original interpreters ran at about 600 instructions per second, 10 per frame
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    pub fn framebuffer(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        self.keys[key & 0xF] = pressed;
+    }
+
+    // The buzzer sounds as long as the sound timer is running
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // What the speaker should play: the buzzer, or on XO-CHIP the audio pattern
+    pub fn sound(&self) -> Sound {
+        Sound {
+            active: self.sound_active(),
+            pattern: self.audio_pattern,
+            pitch: self.pitch
+        }
+    }
+
+    // Whether the program ended itself with 00FD
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    // Identifies the loaded program, see `savestate::hash_rom`
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn i_register(&self) -> u16 {
+        self.i_register
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    // The return addresses currently on the stack, the most recent one last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // Captures everything needed to resume the program later: the registers, the timers,
+    // the stack, the memory, the display, the quirks, the random number generator and the
+    // instruction state (waiting for a key, cycles into the frame, exited). The keys and the
+    // speed are settings of the emulator rather than of the program, they aren't saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.rom_hash);
+        writer.write_u8(variant_code(self.variant));
+        self.quirks.save_state(&mut writer);
+        writer.write_bytes(&self.registers);
+        writer.write_u16(self.i_register);
+        writer.write_u16(self.program_counter);
+        writer.write_u8(self.stack_pointer);
+        for address in &self.stack {
+            writer.write_u16(*address);
+        }
+        writer.write_u8(self.delay_timer);
+        writer.write_u8(self.sound_timer);
+        writer.write_u32(self.frame_cycles);
+        writer.write_u64(self.rng.state());
+        writer.write_u32(self.memory.len() as u32);
+        writer.write_bytes(&self.memory);
+        self.display.save_state(&mut writer);
+        writer.write_bool(self.key_wait.is_some());
+        if let Some(key_wait) = &self.key_wait {
+            writer.write_u8(key_wait.register as u8);
+            // 0xFF while no key has been pressed yet
+            writer.write_u8(key_wait.pressed_key.map_or(0xFF, |key| key as u8));
+        }
+        writer.write_bool(self.exited);
+        writer.write_bytes(&self.rpl_flags);
+        writer.write_bool(self.audio_pattern.is_some());
+        writer.write_bytes(&self.audio_pattern.unwrap_or_default());
+        writer.write_u8(self.pitch);
+        writer.finish()
+    }
+
+    // Restores a state from `save_state`. It must have been made with the same program
+    // and variant, and nothing changes unless the whole state could be read.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(state, self.rom_hash)?;
+        if reader.read_u8()? != variant_code(self.variant) {
+            return Err(SaveStateError::WrongVariant);
+        }
+        let quirks = Quirks::load_state(&mut reader)?;
+        let registers: [u8; 16] = reader.read_bytes(16)?.try_into().unwrap();
+        let i_register = reader.read_u16()?;
+        let program_counter = reader.read_u16()?;
+        let stack_pointer = reader.read_u8()?;
+        if stack_pointer as usize > self.stack.len() {
+            return Err(SaveStateError::Corrupted);
+        }
+        let mut stack = vec![0u16; self.stack.len()];
+        for address in stack.iter_mut() {
+            *address = reader.read_u16()?;
+        }
+        let delay_timer = reader.read_u8()?;
+        let sound_timer = reader.read_u8()?;
+        let frame_cycles = reader.read_u32()?;
+        let rng = Rng::from_state(reader.read_u64()?);
+        if reader.read_u32()? as usize != self.variant.memory_size() {
+            return Err(SaveStateError::Corrupted);
+        }
+        let memory = reader.read_bytes(self.variant.memory_size())?.to_vec();
+        let display = Display::load_state(&mut reader)?;
+        let key_wait = if reader.read_bool()? {
+            let register = reader.read_u8()? as usize;
+            let pressed_key = match reader.read_u8()? {
+                0xFF => None,
+                key => Some(key as usize)
+            };
+            if register >= 16 || pressed_key.is_some_and(|key| key >= 16) {
+                return Err(SaveStateError::Corrupted);
+            }
+            Some(KeyWait { register, pressed_key })
+        } else {
+            None
+        };
+        let exited = reader.read_bool()?;
+        let rpl_flags: [u8; RPL_FLAG_COUNT] = reader.read_bytes(RPL_FLAG_COUNT)?.try_into().unwrap();
+        let has_audio_pattern = reader.read_bool()?;
+        let audio_pattern: [u8; PATTERN_LENGTH] = reader.read_bytes(PATTERN_LENGTH)?.try_into().unwrap();
+        let pitch = reader.read_u8()?;
+        reader.finish()?;
+
+        self.quirks = quirks;
+        self.registers = registers;
+        self.i_register = i_register;
+        self.program_counter = program_counter;
+        self.stack_pointer = stack_pointer;
+        self.stack = stack;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.frame_cycles = frame_cycles;
+        self.rng = rng;
+        self.memory = memory;
+        self.display = display;
+        self.key_wait = key_wait;
+        self.waiting_for_display = false;
+        self.exited = exited;
+        self.rpl_flags = rpl_flags;
+        self.audio_pattern = has_audio_pattern.then_some(audio_pattern);
+        self.pitch = pitch;
+        Ok(())
+    }
+
+    // Runs one 60Hz frame: steps until the timers tick, which takes instructions_per_frame
+    // cycles from the start of a frame. Since the timers are driven by the executed
+    // instructions rather than the wall clock, a program always sees the same timings.
+    pub fn run_frame(&mut self) -> Result<(), EmulatorError> {
+        loop {
+            self.step()?;
+            if self.at_frame_start() {
+                break;
+            }
+            // The machine is stopped, there's nothing left to run in this frame
+            if self.exited {
+                self.end_frame();
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Runs a single cycle: fetches, decodes and executes one instruction, or only checks
+    // the keys while FX0A is waiting, then ticks the timers if the frame is over. With the
+    // display wait quirk, a draw holds the program until the next frame, so the rest of
+    // the frame is skipped. Does nothing once the program has exited.
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        if self.exited {
+            return Ok(());
+        }
+        if self.key_wait.is_some() {
+            self.wait_for_key();
+        } else {
+            let pc = self.program_counter;
+            let opcode = self.fetch()?;
+            let instruction = decode(opcode, self.variant)
+                .map_err(|error| EmulatorError::UnknownOpcode { pc, opcode: error.opcode })?;
+            self.execute(&instruction)?;
+        }
+        self.frame_cycles += 1;
+        if self.waiting_for_display || self.frame_cycles >= self.instructions_per_frame {
+            self.waiting_for_display = false;
+            self.end_frame();
+        }
+        Ok(())
+    }
+
+    // Whether the timers have just ticked, i.e. the last step ended a frame
+    pub fn at_frame_start(&self) -> bool {
+        self.frame_cycles == 0
+    }
+
+    // Skips the rest of the frame and ticks the timers
+    fn end_frame(&mut self) {
+        self.tick_timers();
+        self.frame_cycles = 0;
+    }
+
+    // Reads the opcode at the program counter and moves it to the next instruction
+    fn fetch(&mut self) -> Result<u16, EmulatorError> {
+        let pc = self.program_counter;
+        if pc as usize + 1 >= self.memory.len() {
+            return Err(EmulatorError::ProgramCounterOutOfBounds { pc });
+        }
+        self.advance(pc, 2)?;
+        Ok(self.read_word(pc))
+    }
+
+    // Executes a decoded instruction. The program counter is expected to already point
+    // past it, as it does after fetching, so errors report the address before it.
+    pub fn execute(&mut self, instruction: &Instruction) -> Result<(), EmulatorError> {
+        let pc = self.program_counter.wrapping_sub(2);
+        let opcode = instruction.encode();
+
+        match *instruction {
+            Instruction::Syscall { .. } => {} // Ignored in emulators
+            Instruction::ClearScreen => {
+                self.display.clear();
+            }
+            Instruction::Return => {
+                if self.stack_pointer == 0 {
+                    return Err(EmulatorError::StackUnderflow { pc, opcode });
+                }
+                self.stack_pointer -= 1;
+                self.program_counter = self.stack[self.stack_pointer as usize];
+            }
+            Instruction::ScrollDown { n } => {
+                let distance = self.scroll_distance(n as usize);
+                self.display.scroll_down(distance);
+            }
+            Instruction::ScrollUp { n } => {
+                let distance = self.scroll_distance(n as usize);
+                self.display.scroll_up(distance);
+            }
+            Instruction::ScrollRight => {
+                let distance = self.scroll_distance(4);
+                self.display.scroll_right(distance);
+            }
+            Instruction::ScrollLeft => {
+                let distance = self.scroll_distance(4);
+                self.display.scroll_left(distance);
+            }
+            Instruction::Exit => {
+                self.exited = true;
+            }
+            Instruction::LowResolution => {
+                self.set_resolution(false);
+            }
+            Instruction::HighResolution => {
+                self.set_resolution(true);
+            }
+            Instruction::Jump { address } => {
+                self.program_counter = address;
+            }
+            Instruction::Call { address } => {
+                if self.stack_pointer as usize >= self.stack.len() {
+                    return Err(EmulatorError::StackOverflow { pc, opcode });
+                }
+                self.stack[self.stack_pointer as usize] = self.program_counter;
+                self.stack_pointer += 1;
+                self.program_counter = address;
+            }
+            Instruction::SkipIfEqual { x, value } => {
+                if self.registers[x] == value {
+                    self.skip_next_instruction(pc)?;
+                }
+            }
+            Instruction::SkipIfNotEqual { x, value } => {
+                if self.registers[x] != value {
+                    self.skip_next_instruction(pc)?;
+                }
+            }
+            Instruction::SkipIfRegistersEqual { x, y } => {
+                if self.registers[x] == self.registers[y] {
+                    self.skip_next_instruction(pc)?;
+                }
+            }
+            Instruction::SaveRange { x, y } => {
+                self.check_memory(pc, opcode, register_range(x, y).count())?;
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.memory[self.i_register as usize + offset] = self.registers[register];
+                }
+            }
+            Instruction::LoadRange { x, y } => {
+                self.check_memory(pc, opcode, register_range(x, y).count())?;
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.registers[register] = self.memory[self.i_register as usize + offset];
+                }
+            }
+            Instruction::Load { x, value } => {
+                self.registers[x] = value;
+            }
+            Instruction::Add { x, value } => {
+                self.registers[x] = self.registers[x].wrapping_add(value);
+            }
+            Instruction::Move { x, y } => {
+                self.registers[x] = self.registers[y];
+            }
+            Instruction::Or { x, y } => {
+                self.registers[x] |= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Instruction::And { x, y } => {
+                self.registers[x] &= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Instruction::Xor { x, y } => {
+                self.registers[x] ^= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Instruction::AddRegisters { x, y } => {
+                let (result, carry) = self.registers[x].overflowing_add(self.registers[y]);
+                self.registers[x] = result;
+                self.registers[0xF] = carry as u8;
+            }
+            Instruction::Subtract { x, y } => {
+                let f_value = (self.registers[x] >= self.registers[y]) as u8;
+                self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]);
+                self.registers[0xF] = f_value;
+            }
+            Instruction::ShiftRight { x, y } => {
+                if !self.quirks.shifting {
+                    self.registers[x] = self.registers[y];
+                }
+                let f_value = self.registers[x] & 0x1;
+                self.registers[x] >>= 1;
+                self.registers[0xF] = f_value;
+            }
+            Instruction::SubtractReversed { x, y } => {
+                let f_value = (self.registers[y] >= self.registers[x]) as u8;
+                self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
+                self.registers[0xF] = f_value;
+            }
+            Instruction::ShiftLeft { x, y } => {
+                if !self.quirks.shifting {
+                    self.registers[x] = self.registers[y];
+                }
+                let f_value = self.registers[x] >> 7;
+                self.registers[x] <<= 1;
+                self.registers[0xF] = f_value;
+            }
+            Instruction::SkipIfRegistersNotEqual { x, y } => {
+                if self.registers[x] != self.registers[y] {
+                    self.skip_next_instruction(pc)?;
+                }
+            }
+            Instruction::LoadI { address } => {
+                self.i_register = address;
+            }
+            Instruction::JumpOffset { address } => {
+                let register = if self.quirks.jumping { (address >> 8) as usize } else { 0 };
+                self.program_counter = address.wrapping_add(self.registers[register] as u16);
+            }
+            Instruction::Random { x, mask } => {
+                self.registers[x] = self.rng.next_byte() & mask;
+            }
+            Instruction::Draw { x, y, n } => {
+                self.registers[15] = self.draw_sprite(pc, opcode, x, y, n)?;
+                self.waiting_for_display = self.quirks.display_wait;
+            }
+            Instruction::SkipIfKey { x } => {
+                if self.keys[(self.registers[x] & 0xF) as usize] {
+                    self.skip_next_instruction(pc)?;
+                }
+            }
+            Instruction::SkipIfNotKey { x } => {
+                if !self.keys[(self.registers[x] & 0xF) as usize] {
+                    self.skip_next_instruction(pc)?;
+                }
+            }
+            Instruction::LoadLongI => {
+                if self.program_counter as usize + 1 >= self.memory.len() {
+                    return Err(EmulatorError::MemoryOutOfBounds { pc, opcode, address: self.program_counter as usize });
+                }
+                let address = self.read_word(self.program_counter);
+                self.advance(pc, 2)?;
+                self.i_register = address;
+            }
+            Instruction::SelectPlanes { planes } => {
+                self.display.select_planes(planes as usize);
+            }
+            Instruction::LoadAudioPattern => {
+                self.check_memory(pc, opcode, PATTERN_LENGTH)?;
+                let start = self.i_register as usize;
+                let mut pattern = [0; PATTERN_LENGTH];
+                pattern.copy_from_slice(&self.memory[start..start + PATTERN_LENGTH]);
+                self.audio_pattern = Some(pattern);
+            }
+            Instruction::GetDelayTimer { x } => {
+                self.registers[x] = self.delay_timer;
+            }
+            Instruction::WaitForKey { x } => {
+                self.key_wait = Some(KeyWait {
+                    register: x,
+                    pressed_key: None
+                });
+            }
+            Instruction::SetDelayTimer { x } => {
+                self.delay_timer = self.registers[x];
+            }
+            Instruction::SetSoundTimer { x } => {
+                self.sound_timer = self.registers[x];
+            }
+            Instruction::AddToI { x } => {
+                self.i_register = self.i_register.wrapping_add(self.registers[x] as u16);
+            }
+            Instruction::LoadFont { x } => {
+                // Only the low nibble on every variant, see the readme
+                self.i_register = 5 * (self.registers[x] & 0xF) as u16;
+            }
+            Instruction::LoadBigFont { x } => {
+                let digit = (self.registers[x] & 0xF) as u16;
+                self.i_register = BIG_SPRITES_ADDRESS as u16 + 10 * digit;
+            }
+            Instruction::StoreBcd { x } => {
+                let num = self.registers[x];
+                self.check_memory(pc, opcode, 3)?;
+                self.memory[self.i_register as usize] = num / 100;
+                self.memory[self.i_register as usize + 1] = num % 100 / 10;
+                self.memory[self.i_register as usize + 2] = num % 10;
+            }
+            Instruction::SetPitch { x } => {
+                self.pitch = self.registers[x];
+            }
+            Instruction::StoreRegisters { x } => {
+                self.check_memory(pc, opcode, x + 1)?;
+                for i in 0..=x {
+                    self.memory[self.i_register as usize + i] = self.registers[i];
+                }
+                if self.quirks.memory_increment {
+                    self.i_register = self.i_register.wrapping_add((x + 1) as u16);
+                }
+            }
+            Instruction::LoadRegisters { x } => {
+                self.check_memory(pc, opcode, x + 1)?;
+                for i in 0..=x {
+                    self.registers[i] = self.memory[self.i_register as usize + i];
+                }
+                if self.quirks.memory_increment {
+                    self.i_register = self.i_register.wrapping_add((x + 1) as u16);
+                }
+            }
+            Instruction::SaveFlags { x } => {
+                for i in 0..=x.min(self.rpl_flag_count() - 1) {
+                    self.rpl_flags[i] = self.registers[i];
+                }
+                self.rpl_flags_dirty = true;
+            }
+            Instruction::LoadFlags { x } => {
+                for i in 0..=x.min(self.rpl_flag_count() - 1) {
+                    self.registers[i] = self.rpl_flags[i];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn wait_for_key(&mut self) {
+        let Some(key_wait) = &mut self.key_wait else {
+            return;
+        };
+        match key_wait.pressed_key {
+            None => {
+                key_wait.pressed_key = self.keys.iter().position(|pressed| *pressed);
+            }
+            Some(key) => {
+                if !self.keys[key] {
+                    self.registers[key_wait.register] = key as u8;
+                    self.key_wait = None;
+                }
+            }
+        }
+    }
+
+    fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // Checks that the `length` bytes at I are inside memory
+    fn check_memory(&self, pc: u16, opcode: u16, length: usize) -> Result<(), EmulatorError> {
+        let address = self.i_register as usize;
+        if address + length > self.memory.len() {
+            return Err(EmulatorError::MemoryOutOfBounds { pc, opcode, address });
+        }
+        Ok(())
+    }
+
+    fn read_word(&self, address: u16) -> u16 {
+        (self.memory[address as usize] as u16) << 8 | self.memory[address as usize + 1] as u16
+    }
+
+    // Moves the program counter `length` bytes forward. On XO-CHIP the last instruction
+    // of memory would move it past 0xFFFF, which is reported as leaving memory.
+    fn advance(&mut self, pc: u16, length: u16) -> Result<(), EmulatorError> {
+        self.program_counter = self.program_counter.checked_add(length)
+            .ok_or(EmulatorError::ProgramCounterOutOfBounds { pc })?;
+        Ok(())
+    }
+
+    // On XO-CHIP the instruction being skipped may be the 4 bytes long F000 NNNN
+    fn skip_next_instruction(&mut self, pc: u16) -> Result<(), EmulatorError> {
+        let next = self.program_counter as usize;
+        let is_long = self.variant.is_xo_chip()
+            && next + 1 < self.memory.len()
+            && self.read_word(self.program_counter) == 0xF000;
+        self.advance(pc, if is_long { 4 } else { 2 })
+    }
+
+    // The HP-48 calculators only had 8 RPL user flags, XO-CHIP extends them to 16
+    fn rpl_flag_count(&self) -> usize {
+        match self.variant {
+            Variant::XoChip => 16,
+            _ => 8
+        }
+    }
+
+    // XO-CHIP clears the whole screen when switching resolution
+    fn set_resolution(&mut self, hires: bool) {
+        self.display.set_hires(hires);
+        if self.variant.is_xo_chip() {
+            self.display.clear_all();
+        }
+    }
+
+    // Converts a scroll distance in pixels into physical framebuffer pixels.
+    // The original SUPER-CHIP 1.1 always scrolls by high resolution pixels, so in low
+    // resolution it only moves the image by half a pixel per step. Modern interpreters
+    // scroll by pixels of the active resolution instead.
+    fn scroll_distance(&self, pixels: usize) -> usize {
+        match self.variant {
+            Variant::SuperChip => pixels,
+            _ => pixels * self.display.pixel_size()
+        }
+    }
+
+    // Draws the sprite at I and returns the value for VF.
+    // On SUPER-CHIP, DXY0 draws a 16x16 sprite made of 2 bytes per row
+    // (an 8x16 one in low resolution on the original SUPER-CHIP 1.1).
+    // When several XO-CHIP planes are selected, the sprite data for each plane follows the previous one.
+    fn draw_sprite(&mut self, pc: u16, opcode: u16, x: usize, y: usize, n: u8) -> Result<u8, EmulatorError> {
+        let mut collided_rows = 0;
+        let mut clipped_rows = 0;
+
+        let (width, height) = (self.display.width(), self.display.height());
+
+        // The starting position always wraps around the screen
+        let initial_x = self.registers[x] as usize % width;
+        let initial_y = self.registers[y] as usize % height;
+        let (rows, sprite_width) = match n {
+            0 if self.variant.is_super_chip() => {
+                if matches!(self.variant, Variant::SuperChip) && !self.display.is_hires() {
+                    (16, 8)
+                } else {
+                    (16, 16)
+                }
+            }
+            _ => (n as usize, 8)
+        };
+        let bytes_per_row = sprite_width / 8;
+        let selected_planes = self.display.planes();
+        let plane_count = selected_planes.count_ones() as usize;
+        self.check_memory(pc, opcode, rows * bytes_per_row * plane_count)?;
+
+        let mut sprite_address = self.i_register as usize;
+        for plane in (0..PLANE_COUNT).map(|p| 1 << p).filter(|p| selected_planes & p != 0) {
+            for row in 0..rows {
+                let y = initial_y + row;
+                if self.quirks.clipping && y >= height {
+                    clipped_rows += 1;
+                    continue;
+                }
+                let address = sprite_address + row * bytes_per_row;
+                // The sprite line is left-aligned in 16 bits so that both widths share the loop below
+                let sprite_line = if bytes_per_row == 2 {
+                    (self.memory[address] as u16) << 8 | self.memory[address + 1] as u16
+                } else {
+                    (self.memory[address] as u16) << 8
+                };
+                let mut row_collision = false;
+                for n in 0..sprite_width {
+                    let x = initial_x + n;
+                    let bit = (sprite_line >> (15 - n)) & 1;
+                    if self.quirks.clipping && x >= width {
+                        continue;
+                    }
+                    // Without clipping, modulus is used to wrap to the other side.
+                    // For example, if attempting to write at [34][67] in low resolution
+                    // they become -> [2][3] instead
+                    if bit == 1 && self.display.toggle(x % width, y % height, plane) {
+                        row_collision = true;
+                    }
+                }
+                if row_collision {
+                    collided_rows += 1;
+                }
+            }
+            sprite_address += rows * bytes_per_row;
+        }
+
+        // SUPER-CHIP 1.1 reports the number of rows that collided or were clipped
+        // at the bottom of the screen when in high resolution
+        if matches!(self.variant, Variant::SuperChip) && self.display.is_hires() {
+            Ok(collided_rows + clipped_rows)
+        } else {
+            Ok((collided_rows > 0) as u8)
+        }
+    }
+}
+
+// A program too large for the memory of the variant
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RomTooLarge {
+    pub size: usize,
+    pub capacity: usize
+}
+
+impl fmt::Display for RomTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The program is {} bytes long, but only {} bytes fit in memory", self.size, self.capacity)
+    }
+}
+
+impl std::error::Error for RomTooLarge {}
+
+// How save states tell the variants apart
+fn variant_code(variant: Variant) -> u8 {
+    match variant {
+        Variant::CosmacVip => 0,
+        Variant::SuperChip => 1,
+        Variant::SuperChipExtended => 2,
+        Variant::XoChip => 3
+    }
+}
+
+// Registers X to Y of 5XY2/5XY3, in descending order when Y is smaller than X
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::str::FromStr;
+use crate::chip8::PROGRAM_START;
+use crate::instruction::{decode, Instruction};
+use crate::quirks::Quirks;
+use crate::variant::Variant;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    // The syntax of the Octo assembler, e.g. `v0 := 0x12`
+    Octo,
+    // The mnemonics of Cowgod's Chip-8 technical reference, e.g. `LD V0, #12`
+    Cowgod
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Syntax, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "octo" => Ok(Syntax::Octo),
+            "cowgod" => Ok(Syntax::Cowgod),
+            _ => Err(format!("Unknown syntax {}, expected octo or cowgod", name))
+        }
+    }
+}
+
+// What an address is used for, to name its label. When an address is used in several
+// ways, the kind listed first wins.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Label {
+    Subroutine,
+    Jump,
+    Data
+}
+
+// Returns an annotated listing of a ROM: one line per instruction or group of data
+// bytes, with its address and raw bytes, and labels for the jump, call and I targets.
+// Code is told apart from data by following the control flow from the start of the
+// program, everything that can't be reached that way is listed as data.
+pub fn disassemble(rom: &[u8], variant: Variant, syntax: Syntax) -> String {
+    let disassembly = Disassembly::trace(rom, variant);
+    let mut listing = String::new();
+    let end = PROGRAM_START + rom.len();
+    let mut address = PROGRAM_START;
+    while address < end {
+        if let Some(label) = disassembly.labels.get(&address) {
+            let name = label_name(*label, address);
+            match syntax {
+                Syntax::Octo => writeln!(listing, ": {}", name).unwrap(),
+                Syntax::Cowgod => writeln!(listing, "{}:", name).unwrap()
+            }
+        }
+        let (length, text) = match disassembly.instruction_at(address) {
+            Some(instruction) => {
+                let length = instruction_length(&instruction);
+                (length, disassembly.format(&instruction, address, syntax))
+            }
+            None => {
+                // Data runs until the next instruction or label, 4 bytes per line at most
+                let mut length = 1;
+                while length < 4 && address + length < end
+                    && !disassembly.code.contains(&(address + length))
+                    && !disassembly.labels.contains_key(&(address + length)) {
+                    length += 1;
+                }
+                (length, format_data(&rom[address - PROGRAM_START..address - PROGRAM_START + length], syntax))
+            }
+        };
+        let bytes: Vec<String> = rom[address - PROGRAM_START..address - PROGRAM_START + length]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        writeln!(listing, "{:04X}  {:<13}{}", address, bytes.join(" "), text).unwrap();
+        address += length;
+    }
+    listing
+}
+
+struct Disassembly<'a> {
+    rom: &'a [u8],
+    variant: Variant,
+    // BNNN is read as BXNN with the jumping quirk of the variant
+    jumping: bool,
+    // Addresses where a reachable instruction starts
+    code: BTreeSet<usize>,
+    labels: BTreeMap<usize, Label>
+}
+
+impl<'a> Disassembly<'a> {
+    fn trace(rom: &'a [u8], variant: Variant) -> Disassembly<'a> {
+        let mut disassembly = Disassembly {
+            rom,
+            variant,
+            jumping: Quirks::for_variant(&variant).jumping,
+            code: BTreeSet::new(),
+            labels: BTreeMap::new()
+        };
+        let mut pending = vec![PROGRAM_START];
+        while let Some(address) = pending.pop() {
+            if disassembly.code.contains(&address) {
+                continue;
+            }
+            let Some(instruction) = disassembly.decode_at(address) else {
+                continue;
+            };
+            disassembly.code.insert(address);
+            let next = address + instruction_length(&instruction);
+            match instruction {
+                Instruction::Jump { address: target } => {
+                    disassembly.add_label(target as usize, Label::Jump);
+                    pending.push(target as usize);
+                }
+                Instruction::Call { address: target } => {
+                    disassembly.add_label(target as usize, Label::Subroutine);
+                    pending.push(target as usize);
+                    pending.push(next);
+                }
+                // The target of BNNN depends on a register, so it can't be followed
+                Instruction::Return | Instruction::Exit | Instruction::JumpOffset { .. } => {}
+                Instruction::SkipIfEqual { .. }
+                | Instruction::SkipIfNotEqual { .. }
+                | Instruction::SkipIfRegistersEqual { .. }
+                | Instruction::SkipIfRegistersNotEqual { .. }
+                | Instruction::SkipIfKey { .. }
+                | Instruction::SkipIfNotKey { .. } => {
+                    pending.push(next);
+                    let skipped_length = match disassembly.decode_at(next) {
+                        Some(skipped) if variant.is_xo_chip() => instruction_length(&skipped),
+                        _ => 2
+                    };
+                    pending.push(next + skipped_length);
+                }
+                Instruction::LoadI { address: target } => {
+                    disassembly.add_label(target as usize, Label::Data);
+                    pending.push(next);
+                }
+                Instruction::LoadLongI => {
+                    let target = disassembly.word_at(address + 2).unwrap();
+                    disassembly.add_label(target as usize, Label::Data);
+                    pending.push(next);
+                }
+                _ => pending.push(next)
+            }
+        }
+        disassembly
+    }
+
+    fn word_at(&self, address: usize) -> Option<u16> {
+        let offset = address.checked_sub(PROGRAM_START)?;
+        if offset + 1 >= self.rom.len() {
+            return None;
+        }
+        Some((self.rom[offset] as u16) << 8 | self.rom[offset + 1] as u16)
+    }
+
+    // Decodes the instruction at an address, as long as all its bytes are inside the ROM
+    fn decode_at(&self, address: usize) -> Option<Instruction> {
+        let instruction = decode(self.word_at(address)?, self.variant).ok()?;
+        if instruction == Instruction::LoadLongI {
+            self.word_at(address + 2)?;
+        }
+        Some(instruction)
+    }
+
+    fn instruction_at(&self, address: usize) -> Option<Instruction> {
+        if self.code.contains(&address) {
+            self.decode_at(address)
+        } else {
+            None
+        }
+    }
+
+    // Only addresses inside the ROM get a label, the others are printed as numbers
+    fn add_label(&mut self, address: usize, label: Label) {
+        if address < PROGRAM_START || address >= PROGRAM_START + self.rom.len() {
+            return;
+        }
+        let entry = self.labels.entry(address).or_insert(label);
+        if label < *entry {
+            *entry = label;
+        }
+    }
+
+    fn format(&self, instruction: &Instruction, address: usize, syntax: Syntax) -> String {
+        let target = |target: u16| match self.labels.get(&(target as usize)) {
+            Some(label) => label_name(*label, target as usize),
+            None => match syntax {
+                Syntax::Octo => format!("0x{:03X}", target),
+                Syntax::Cowgod => format!("#{:03X}", target)
+            }
+        };
+        match syntax {
+            Syntax::Octo => {
+                let v = |x: usize| format!("v{:x}", x);
+                match *instruction {
+                    Instruction::Syscall { address } => format!("0x{:02X} 0x{:02X} # machine code routine", address >> 8, address & 0xFF),
+                    Instruction::ClearScreen => "clear".to_string(),
+                    Instruction::Return => "return".to_string(),
+                    Instruction::ScrollDown { n } => format!("scroll-down {}", n),
+                    Instruction::ScrollUp { n } => format!("scroll-up {}", n),
+                    Instruction::ScrollRight => "scroll-right".to_string(),
+                    Instruction::ScrollLeft => "scroll-left".to_string(),
+                    Instruction::Exit => "exit".to_string(),
+                    Instruction::LowResolution => "lores".to_string(),
+                    Instruction::HighResolution => "hires".to_string(),
+                    Instruction::Jump { address } => format!("jump {}", target(address)),
+                    Instruction::Call { address } => format!(":call {}", target(address)),
+                    // Octo's conditionals hold the condition for running the next instruction,
+                    // which is the opposite of the skip condition
+                    Instruction::SkipIfEqual { x, value } => format!("if {} != 0x{:02X} then", v(x), value),
+                    Instruction::SkipIfNotEqual { x, value } => format!("if {} == 0x{:02X} then", v(x), value),
+                    Instruction::SkipIfRegistersEqual { x, y } => format!("if {} != {} then", v(x), v(y)),
+                    Instruction::SaveRange { x, y } => format!("save {} - {}", v(x), v(y)),
+                    Instruction::LoadRange { x, y } => format!("load {} - {}", v(x), v(y)),
+                    Instruction::Load { x, value } => format!("{} := 0x{:02X}", v(x), value),
+                    Instruction::Add { x, value } => format!("{} += 0x{:02X}", v(x), value),
+                    Instruction::Move { x, y } => format!("{} := {}", v(x), v(y)),
+                    Instruction::Or { x, y } => format!("{} |= {}", v(x), v(y)),
+                    Instruction::And { x, y } => format!("{} &= {}", v(x), v(y)),
+                    Instruction::Xor { x, y } => format!("{} ^= {}", v(x), v(y)),
+                    Instruction::AddRegisters { x, y } => format!("{} += {}", v(x), v(y)),
+                    Instruction::Subtract { x, y } => format!("{} -= {}", v(x), v(y)),
+                    Instruction::ShiftRight { x, y } => format!("{} >>= {}", v(x), v(y)),
+                    Instruction::SubtractReversed { x, y } => format!("{} =- {}", v(x), v(y)),
+                    Instruction::ShiftLeft { x, y } => format!("{} <<= {}", v(x), v(y)),
+                    Instruction::SkipIfRegistersNotEqual { x, y } => format!("if {} == {} then", v(x), v(y)),
+                    Instruction::LoadI { address } => format!("i := {}", target(address)),
+                    Instruction::JumpOffset { address } if self.jumping => {
+                        format!("jump0 {} # read as BXNN, jumps to 0x{:03X} + {}, not followed", target(address), address, v(address as usize >> 8))
+                    }
+                    Instruction::JumpOffset { address } => format!("jump0 {} # computed jump, not followed", target(address)),
+                    Instruction::Random { x, mask } => format!("{} := random 0x{:02X}", v(x), mask),
+                    Instruction::Draw { x, y, n } => format!("sprite {} {} {}", v(x), v(y), n),
+                    Instruction::SkipIfKey { x } => format!("if {} -key then", v(x)),
+                    Instruction::SkipIfNotKey { x } => format!("if {} key then", v(x)),
+                    Instruction::LoadLongI => format!("i := long {}", target(self.word_at(address + 2).unwrap())),
+                    Instruction::SelectPlanes { planes } => format!("plane {}", planes),
+                    Instruction::LoadAudioPattern => "audio".to_string(),
+                    Instruction::GetDelayTimer { x } => format!("{} := delay", v(x)),
+                    Instruction::WaitForKey { x } => format!("{} := key", v(x)),
+                    Instruction::SetDelayTimer { x } => format!("delay := {}", v(x)),
+                    Instruction::SetSoundTimer { x } => format!("buzzer := {}", v(x)),
+                    Instruction::AddToI { x } => format!("i += {}", v(x)),
+                    Instruction::LoadFont { x } => format!("i := hex {}", v(x)),
+                    Instruction::LoadBigFont { x } => format!("i := bighex {}", v(x)),
+                    Instruction::StoreBcd { x } => format!("bcd {}", v(x)),
+                    Instruction::SetPitch { x } => format!("pitch := {}", v(x)),
+                    Instruction::StoreRegisters { x } => format!("save {}", v(x)),
+                    Instruction::LoadRegisters { x } => format!("load {}", v(x)),
+                    Instruction::SaveFlags { x } => format!("saveflags {}", v(x)),
+                    Instruction::LoadFlags { x } => format!("loadflags {}", v(x)),
+                }
+            }
+            Syntax::Cowgod => {
+                let v = |x: usize| format!("V{:X}", x);
+                match *instruction {
+                    Instruction::Syscall { address } => format!("SYS {}", target(address)),
+                    Instruction::ClearScreen => "CLS".to_string(),
+                    Instruction::Return => "RET".to_string(),
+                    Instruction::ScrollDown { n } => format!("SCD {}", n),
+                    Instruction::ScrollUp { n } => format!("SCU {}", n),
+                    Instruction::ScrollRight => "SCR".to_string(),
+                    Instruction::ScrollLeft => "SCL".to_string(),
+                    Instruction::Exit => "EXIT".to_string(),
+                    Instruction::LowResolution => "LOW".to_string(),
+                    Instruction::HighResolution => "HIGH".to_string(),
+                    Instruction::Jump { address } => format!("JP {}", target(address)),
+                    Instruction::Call { address } => format!("CALL {}", target(address)),
+                    Instruction::SkipIfEqual { x, value } => format!("SE {}, #{:02X}", v(x), value),
+                    Instruction::SkipIfNotEqual { x, value } => format!("SNE {}, #{:02X}", v(x), value),
+                    Instruction::SkipIfRegistersEqual { x, y } => format!("SE {}, {}", v(x), v(y)),
+                    Instruction::SaveRange { x, y } => format!("SAVE {} - {}", v(x), v(y)),
+                    Instruction::LoadRange { x, y } => format!("LOAD {} - {}", v(x), v(y)),
+                    Instruction::Load { x, value } => format!("LD {}, #{:02X}", v(x), value),
+                    Instruction::Add { x, value } => format!("ADD {}, #{:02X}", v(x), value),
+                    Instruction::Move { x, y } => format!("LD {}, {}", v(x), v(y)),
+                    Instruction::Or { x, y } => format!("OR {}, {}", v(x), v(y)),
+                    Instruction::And { x, y } => format!("AND {}, {}", v(x), v(y)),
+                    Instruction::Xor { x, y } => format!("XOR {}, {}", v(x), v(y)),
+                    Instruction::AddRegisters { x, y } => format!("ADD {}, {}", v(x), v(y)),
+                    Instruction::Subtract { x, y } => format!("SUB {}, {}", v(x), v(y)),
+                    Instruction::ShiftRight { x, y } => format!("SHR {}, {}", v(x), v(y)),
+                    Instruction::SubtractReversed { x, y } => format!("SUBN {}, {}", v(x), v(y)),
+                    Instruction::ShiftLeft { x, y } => format!("SHL {}, {}", v(x), v(y)),
+                    Instruction::SkipIfRegistersNotEqual { x, y } => format!("SNE {}, {}", v(x), v(y)),
+                    Instruction::LoadI { address } => format!("LD I, {}", target(address)),
+                    Instruction::JumpOffset { address } if self.jumping => {
+                        format!("JP {}, #{:02X} ; computed jump, not followed", v(address as usize >> 8), address & 0xFF)
+                    }
+                    Instruction::JumpOffset { address } => format!("JP V0, {} ; computed jump, not followed", target(address)),
+                    Instruction::Random { x, mask } => format!("RND {}, #{:02X}", v(x), mask),
+                    Instruction::Draw { x, y, n } => format!("DRW {}, {}, {}", v(x), v(y), n),
+                    Instruction::SkipIfKey { x } => format!("SKP {}", v(x)),
+                    Instruction::SkipIfNotKey { x } => format!("SKNP {}", v(x)),
+                    Instruction::LoadLongI => format!("LD I, LONG {}", target(self.word_at(address + 2).unwrap())),
+                    Instruction::SelectPlanes { planes } => format!("PLANE {}", planes),
+                    Instruction::LoadAudioPattern => "AUDIO".to_string(),
+                    Instruction::GetDelayTimer { x } => format!("LD {}, DT", v(x)),
+                    Instruction::WaitForKey { x } => format!("LD {}, K", v(x)),
+                    Instruction::SetDelayTimer { x } => format!("LD DT, {}", v(x)),
+                    Instruction::SetSoundTimer { x } => format!("LD ST, {}", v(x)),
+                    Instruction::AddToI { x } => format!("ADD I, {}", v(x)),
+                    Instruction::LoadFont { x } => format!("LD F, {}", v(x)),
+                    Instruction::LoadBigFont { x } => format!("LD HF, {}", v(x)),
+                    Instruction::StoreBcd { x } => format!("LD B, {}", v(x)),
+                    Instruction::SetPitch { x } => format!("PITCH {}", v(x)),
+                    Instruction::StoreRegisters { x } => format!("LD [I], {}", v(x)),
+                    Instruction::LoadRegisters { x } => format!("LD {}, [I]", v(x)),
+                    Instruction::SaveFlags { x } => format!("LD R, {}", v(x)),
+                    Instruction::LoadFlags { x } => format!("LD {}, R", v(x)),
+                }
+            }
+        }
+    }
+}
+
+fn instruction_length(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::LoadLongI => 4,
+        _ => 2
+    }
+}
+
+fn label_name(label: Label, address: usize) -> String {
+    match label {
+        Label::Subroutine => format!("sub_{:04X}", address),
+        Label::Jump => format!("label_{:04X}", address),
+        Label::Data => format!("data_{:04X}", address)
+    }
+}
+
+fn format_data(bytes: &[u8], syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Octo => {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+            bytes.join(" ")
+        }
+        Syntax::Cowgod => {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("#{:02X}", byte)).collect();
+            format!("DB {}", bytes.join(", "))
+        }
+    }
+}
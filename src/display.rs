@@ -0,0 +1,170 @@
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+// The framebuffer is always stored at the SUPER-CHIP high resolution (128x64).
+// In low resolution mode every logical pixel covers a 2x2 block of physical
+// pixels, which keeps the image the same size on screen regardless of the mode.
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+
+// XO-CHIP has two bitplanes: each pixel holds one bit per plane, giving four colours.
+// Drawing, clearing and scrolling only affect the planes selected with FN01.
+pub const PLANE_COUNT: usize = 2;
+
+#[derive(Clone)]
+pub struct Display {
+    hires: bool,
+    planes: usize,
+    pixels: [[usize; WIDTH]; HEIGHT],
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            hires: false,
+            planes: 1,
+            pixels: [[0; WIDTH]; HEIGHT],
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+    }
+
+    // Bit mask of the selected planes, plane 1 being the lowest bit
+    pub fn planes(&self) -> usize {
+        self.planes
+    }
+
+    pub fn select_planes(&mut self, planes: usize) {
+        self.planes = planes & ((1 << PLANE_COUNT) - 1);
+    }
+
+    // Width and height of the active resolution, in logical pixels
+    pub fn width(&self) -> usize {
+        if self.hires { WIDTH } else { WIDTH / 2 }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HEIGHT } else { HEIGHT / 2 }
+    }
+
+    // Clears the selected planes
+    pub fn clear(&mut self) {
+        for row in self.pixels.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !self.planes;
+            }
+        }
+    }
+
+    pub fn clear_all(&mut self) {
+        self.pixels = [[0; WIDTH]; HEIGHT];
+    }
+
+    // The framebuffer as stored, always at 128x64
+    pub fn pixels(&self) -> &[[usize; WIDTH]; HEIGHT] {
+        &self.pixels
+    }
+
+    // Pixels are saved one byte each, which is plenty for the 2 bits of the planes
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.hires);
+        writer.write_u8(self.planes as u8);
+        for row in self.pixels.iter() {
+            writer.write_bytes(&row.map(|pixel| pixel as u8));
+        }
+    }
+
+    pub fn load_state(reader: &mut StateReader) -> Result<Display, SaveStateError> {
+        let mut display = Display::new();
+        display.hires = reader.read_bool()?;
+        display.planes = reader.read_u8()? as usize;
+        let all_planes = (1 << PLANE_COUNT) - 1;
+        if display.planes > all_planes {
+            return Err(SaveStateError::Corrupted);
+        }
+        for row in display.pixels.iter_mut() {
+            for (pixel, byte) in row.iter_mut().zip(reader.read_bytes(WIDTH)?) {
+                if *byte as usize > all_planes {
+                    return Err(SaveStateError::Corrupted);
+                }
+                *pixel = *byte as usize;
+            }
+        }
+        Ok(display)
+    }
+
+    // XORs a logical pixel of the active resolution on the given plane and returns true
+    // if any of the physical pixels it covers was already lit on that plane.
+    pub fn toggle(&mut self, x: usize, y: usize, plane: usize) -> bool {
+        let scale = self.pixel_size();
+        let mut collision = false;
+        for row in y * scale..(y + 1) * scale {
+            for col in x * scale..(x + 1) * scale {
+                if self.pixels[row][col] & plane != 0 {
+                    collision = true;
+                }
+                self.pixels[row][col] ^= plane;
+            }
+        }
+        collision
+    }
+
+    // Size of a logical pixel of the active resolution, in physical pixels
+    pub fn pixel_size(&self) -> usize {
+        if self.hires { 1 } else { 2 }
+    }
+
+    // The scroll functions move the selected planes by a number of physical pixels,
+    // filling the uncovered area with blank pixels.
+    pub fn scroll_down(&mut self, rows: usize) {
+        for row in (0..HEIGHT).rev() {
+            for col in 0..WIDTH {
+                let source = if row >= rows { self.pixels[row - rows][col] } else { 0 };
+                self.set_selected(row, col, source);
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                let source = if row + rows < HEIGHT { self.pixels[row + rows][col] } else { 0 };
+                self.set_selected(row, col, source);
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        for row in 0..HEIGHT {
+            for col in (0..WIDTH).rev() {
+                let source = if col >= columns { self.pixels[row][col - columns] } else { 0 };
+                self.set_selected(row, col, source);
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                let source = if col + columns < WIDTH { self.pixels[row][col + columns] } else { 0 };
+                self.set_selected(row, col, source);
+            }
+        }
+    }
+
+    // Copies the selected planes of `source` into a physical pixel, leaving the others untouched
+    fn set_selected(&mut self, row: usize, col: usize, source: usize) {
+        self.pixels[row][col] = (self.pixels[row][col] & !self.planes) | (source & self.planes);
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt::Write;
+use crate::chip8::Chip8;
+use crate::display::{Display, HEIGHT, WIDTH};
+
+// Characters for each pixel value in ASCII dumps: blank, plane 1, plane 2, both planes
+const ASCII_PIXELS: [char; 4] = ['.', '#', 'o', '@'];
+
+// Colours for each pixel value in PNG dumps, the same as in the GUI but on a black background
+const PNG_PALETTE: [[u8; 3]; 4] = [
+    [0, 0, 0],
+    [255, 255, 255],
+    [255, 204, 0],
+    [255, 102, 0],
+];
+
+// The framebuffer as text, one line per row. It is always dumped at 128x64, so
+// every low resolution pixel shows up as a 2x2 block.
+pub fn framebuffer_to_ascii(display: &Display) -> String {
+    let mut ascii = String::with_capacity((WIDTH + 1) * HEIGHT);
+    for row in display.pixels() {
+        for pixel in row {
+            ascii.push(ASCII_PIXELS[pixel & 0b11]);
+        }
+        ascii.push('\n');
+    }
+    ascii
+}
+
+// The framebuffer as a 128x64 indexed-colour PNG file
+pub fn framebuffer_to_png(display: &Display) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+    // 8 bits per pixel, indexed colours, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    // Each row starts with its filter type, 0 for none
+    let mut image = Vec::with_capacity((WIDTH + 1) * HEIGHT);
+    for row in display.pixels() {
+        image.push(0);
+        image.extend(row.iter().map(|pixel| (pixel & 0b11) as u8));
+    }
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"PLTE", PNG_PALETTE.as_flattened());
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&image));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// The registers, timers and stack as a JSON object
+pub fn registers_to_json(chip8: &Chip8) -> String {
+    let list = |values: &mut dyn Iterator<Item = u16>| {
+        values.map(|value| value.to_string()).collect::<Vec<String>>().join(", ")
+    };
+    let mut json = String::new();
+    writeln!(json, "{{").unwrap();
+    writeln!(json, "  \"v\": [{}],", list(&mut chip8.registers().iter().map(|v| *v as u16))).unwrap();
+    writeln!(json, "  \"i\": {},", chip8.i_register()).unwrap();
+    writeln!(json, "  \"pc\": {},", chip8.program_counter()).unwrap();
+    writeln!(json, "  \"stack\": [{}],", list(&mut chip8.stack().iter().copied())).unwrap();
+    writeln!(json, "  \"delay_timer\": {},", chip8.delay_timer()).unwrap();
+    writeln!(json, "  \"sound_timer\": {},", chip8.sound_timer()).unwrap();
+    writeln!(json, "  \"hires\": {},", chip8.framebuffer().is_hires()).unwrap();
+    writeln!(json, "  \"exited\": {}", chip8.has_exited()).unwrap();
+    writeln!(json, "}}").unwrap();
+    json
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc32(&[kind.as_slice(), data].concat()).to_be_bytes());
+}
+
+// A zlib stream made of uncompressed deflate blocks, which keeps the encoder tiny.
+// A framebuffer is only 8KiB anyway.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        zlib.push(last as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend_from_slice(&(b << 16 | a).to_be_bytes());
+    zlib
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
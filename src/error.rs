@@ -0,0 +1,59 @@
+use std::fmt;
+
+// Errors that stop the emulator. Each one records the address of the instruction
+// that caused it and its opcode, so the faulty part of the program can be found.
+#[derive(Debug)]
+pub enum EmulatorError {
+    // 2NNN with all 16 stack slots in use
+    StackOverflow { pc: u16, opcode: u16 },
+    // 00EE with an empty stack
+    StackUnderflow { pc: u16, opcode: u16 },
+    // An instruction accessing memory past its end, starting at `address`
+    MemoryOutOfBounds { pc: u16, opcode: u16, address: usize },
+    UnknownOpcode { pc: u16, opcode: u16 },
+    // The program counter left memory, so there is no opcode to report
+    ProgramCounterOutOfBounds { pc: u16 },
+}
+
+impl EmulatorError {
+    pub fn pc(&self) -> u16 {
+        match self {
+            EmulatorError::StackOverflow { pc, .. }
+            | EmulatorError::StackUnderflow { pc, .. }
+            | EmulatorError::MemoryOutOfBounds { pc, .. }
+            | EmulatorError::UnknownOpcode { pc, .. }
+            | EmulatorError::ProgramCounterOutOfBounds { pc } => *pc,
+        }
+    }
+
+    pub fn opcode(&self) -> Option<u16> {
+        match self {
+            EmulatorError::StackOverflow { opcode, .. }
+            | EmulatorError::StackUnderflow { opcode, .. }
+            | EmulatorError::MemoryOutOfBounds { opcode, .. }
+            | EmulatorError::UnknownOpcode { opcode, .. } => Some(*opcode),
+            EmulatorError::ProgramCounterOutOfBounds { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::StackOverflow { .. } => write!(f, "Stack overflow")?,
+            EmulatorError::StackUnderflow { .. } => write!(f, "Return with an empty stack")?,
+            EmulatorError::MemoryOutOfBounds { address, .. } => {
+                write!(f, "Memory access out of bounds at {:#06x}", address)?
+            }
+            EmulatorError::UnknownOpcode { .. } => write!(f, "Unknown instruction")?,
+            EmulatorError::ProgramCounterOutOfBounds { .. } => write!(f, "Program counter out of memory")?,
+        }
+        write!(f, " (PC {:#06x}", self.pc())?;
+        if let Some(opcode) = self.opcode() {
+            write!(f, ", opcode {:#06x}", opcode)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::error::Error for EmulatorError {}
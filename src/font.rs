@@ -0,0 +1,5 @@
+// The hexadecimal digits 0 to F, 4x5 pixels each, loaded at the start of memory for FX29
+pub const SPRITES: [u8;80] = [0xF0,0x90,0x90,0x90,0xF0, 0x20,0x60,0x20,0x20,0x70, 0xF0,0x10,0xF0,0x80,0xF0, 0xF0,0x10,0xF0,0x10,0xF0, 0x90,0x90,0xF0,0x10,0x10, 0xF0,0x80,0xF0,0x10,0xF0, 0xF0,0x80,0xF0,0x90,0xF0, 0xF0,0x10,0x20,0x40,0x40, 0xF0,0x90,0xF0,0x90,0xF0, 0xF0,0x90,0xF0,0x10,0xF0, 0xF0,0x90,0xF0,0x90,0x90, 0xE0,0x90,0xE0,0x90,0xE0, 0xF0,0x80,0x80,0x80,0xF0, 0xE0,0x90,0x90,0x90,0xE0, 0xF0,0x80,0xF0,0x80,0xF0, 0xF0,0x80,0xF0,0x80,0x80];
+// 8x10 SUPER-CHIP font used by FX30, loaded right after the small one
+pub const BIG_SPRITES_ADDRESS: usize = SPRITES.len();
+pub const BIG_SPRITES: [u8;160] = [0x3C,0x7E,0xE7,0xC3,0xC3,0xC3,0xC3,0xE7,0x7E,0x3C, 0x18,0x38,0x58,0x18,0x18,0x18,0x18,0x18,0x18,0x3C, 0x3E,0x7F,0xC3,0x06,0x0C,0x18,0x30,0x60,0xFF,0xFF, 0x3C,0x7E,0xC3,0x03,0x0E,0x0E,0x03,0xC3,0x7E,0x3C, 0x06,0x0E,0x1E,0x36,0x66,0xC6,0xFF,0xFF,0x06,0x06, 0xFF,0xFF,0xC0,0xC0,0xFC,0xFE,0x03,0xC3,0x7E,0x3C, 0x3E,0x7C,0xE0,0xC0,0xFC,0xFE,0xC3,0xC3,0x7E,0x3C, 0xFF,0xFF,0x03,0x06,0x0C,0x18,0x30,0x60,0x60,0x60, 0x3C,0x7E,0xC3,0xC3,0x7E,0x7E,0xC3,0xC3,0x7E,0x3C, 0x3C,0x7E,0xC3,0xC3,0x7F,0x3F,0x03,0x03,0x3E,0x7C, 0x3C,0x7E,0xC3,0xC3,0xFF,0xFF,0xC3,0xC3,0xC3,0xC3, 0xFC,0xFE,0xC3,0xC3,0xFE,0xFE,0xC3,0xC3,0xFE,0xFC, 0x3C,0x7E,0xC3,0xC0,0xC0,0xC0,0xC0,0xC3,0x7E,0x3C, 0xFC,0xFE,0xC3,0xC3,0xC3,0xC3,0xC3,0xC3,0xFE,0xFC, 0xFF,0xFF,0xC0,0xC0,0xFC,0xFC,0xC0,0xC0,0xFF,0xFF, 0xFF,0xFF,0xC0,0xC0,0xFC,0xFC,0xC0,0xC0,0xC0,0xC0];
@@ -1,11 +1,29 @@
 use std::sync::{Arc, RwLock};
 use gl::COLOR_BUFFER_BIT;
 use glfw::{fail_on_errors, Context, WindowMode};
-use crate::Status;
+use rust_chip8_emulator::display::{Display, HEIGHT, WIDTH};
+use crate::{StateRequest, Status};
 
-pub fn run_gui(display_state: Arc<RwLock<[[usize;64];32]>>, pressed_key: Arc<RwLock<[bool;16]>>, status: Arc<RwLock<Status>>) {
+const WINDOW_TITLE: &str = "A Rusty Chip8 Emulator";
+
+// Colour of each pixel value. Pixels only ever hold 1 outside of XO-CHIP, where
+// the value combines the two bitplanes (bit 0 for plane 1, bit 1 for plane 2).
+// Value 0 is never drawn, the background is the clear colour.
+const PALETTE: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [1.0, 1.0, 1.0],
+    [1.0, 0.8, 0.0],
+    [1.0, 0.4, 0.0],
+];
+
+pub fn run_gui(
+    display_state: Arc<RwLock<Display>>,
+    pressed_key: Arc<RwLock<[bool;16]>>,
+    status: Arc<RwLock<Status>>,
+    state_request: Arc<RwLock<Option<StateRequest>>>
+) {
     let mut glfw = glfw::init(fail_on_errors!()).unwrap();
-    let (mut window, events) = glfw.create_window(1280, 640, "A Rusty Chip8 Emulator", WindowMode::Windowed).unwrap();
+    let (mut window, events) = glfw.create_window(1280, 640, WINDOW_TITLE, WindowMode::Windowed).unwrap();
 
     window.make_current();
     window.set_key_polling(true);
@@ -21,6 +39,7 @@ pub fn run_gui(display_state: Arc<RwLock<[[usize;64];32]>>, pressed_key: Arc<RwL
         gl::ClearColor(0.5, 0.5, 0.75, 1.0);
     }
 
+    let mut program_ended = false;
     while !window.should_close() {
         glfw.poll_events();
         for (_, event) in glfw::flush_messages(&events) {
@@ -28,8 +47,40 @@ pub fn run_gui(display_state: Arc<RwLock<[[usize;64];32]>>, pressed_key: Arc<RwL
                 glfw::WindowEvent::Key(glfw::Key::Space, _a, glfw::Action::Press, _c) => {
                     toggle_pause(&status);
                 }
-                glfw::WindowEvent::Key(key, _a, action, _c) => {
-                    update_pressed_key(key, action, pressed_key.clone());
+                glfw::WindowEvent::Key(glfw::Key::Backspace, _a, action, _c) => {
+                    set_rewinding(&status, action != glfw::Action::Release);
+                }
+                glfw::WindowEvent::Key(key, _a, action, modifiers) => {
+                    match (state_slot(key), action) {
+                        (Some(slot), glfw::Action::Press) => {
+                            // Shift+F1-F9 saves to a slot, F1-F9 loads it back
+                            let request = if modifiers.contains(glfw::Modifiers::Shift) {
+                                StateRequest::Save(slot)
+                            } else {
+                                StateRequest::Load(slot)
+                            };
+                            *state_request.write().unwrap() = Some(request);
+                        }
+                        (Some(_), _) => {}
+                        (None, _) => update_pressed_key(key, action, pressed_key.clone())
+                    }
+                }
+                _ => {}
+            }
+        }
+        // The program stopped on its own (SUPER-CHIP 00FD) or because of an error, keep
+        // showing the last frame but make it clear that the emulator is no longer running
+        if !program_ended {
+            match &*status.read().unwrap() {
+                Status::Stopped => {
+                    program_ended = true;
+                    window.set_title(&format!("{} - Program ended", WINDOW_TITLE));
+                    unsafe {gl::ClearColor(0.5, 0.5, 0.5, 1.0);}
+                }
+                Status::Halted(error) => {
+                    program_ended = true;
+                    window.set_title(&format!("{} - Halted: {}", WINDOW_TITLE, error));
+                    unsafe {gl::ClearColor(0.6, 0.3, 0.3, 1.0);}
                 }
                 _ => {}
             }
@@ -59,6 +110,31 @@ fn toggle_pause(status: &Arc<RwLock<Status>>) {
         _ => {}
     }
 }
+// The save state slot of a function key
+fn state_slot(key: glfw::Key) -> Option<u8> {
+    match key {
+        glfw::Key::F1 => Some(1),
+        glfw::Key::F2 => Some(2),
+        glfw::Key::F3 => Some(3),
+        glfw::Key::F4 => Some(4),
+        glfw::Key::F5 => Some(5),
+        glfw::Key::F6 => Some(6),
+        glfw::Key::F7 => Some(7),
+        glfw::Key::F8 => Some(8),
+        glfw::Key::F9 => Some(9),
+        _ => None
+    }
+}
+// Rewinding only happens while the game runs, and it carries on from where it stopped
+// once the key is released
+fn set_rewinding(status: &Arc<RwLock<Status>>, rewinding: bool) {
+    let mut write_status = status.write().unwrap();
+    match (&*write_status, rewinding) {
+        (Status::Running, true) => *write_status = Status::Rewinding,
+        (Status::Rewinding, false) => *write_status = Status::Running,
+        _ => {}
+    }
+}
 fn update_pressed_key(key: glfw::Key, action: glfw::Action, lock: Arc<RwLock<[bool;16]>>) {
     match key {
         glfw::Key::Num1 => {map_key_press(1, action, lock);}
@@ -100,7 +176,7 @@ fn map_key_press(value: usize, action: glfw::Action, lock: Arc<RwLock<[bool;16]>
     }
 }
 
-fn draw_screen(display_state: Arc<RwLock<[[usize;64];32]>>, vao: u32) {
+fn draw_screen(display_state: Arc<RwLock<Display>>, vao: u32) {
     let (display_vector, vertex_count) = convert_state_to_vertices(display_state);
     if vertex_count > 0 {
         unsafe {
@@ -111,46 +187,47 @@ fn draw_screen(display_state: Arc<RwLock<[[usize;64];32]>>, vao: u32) {
                 gl::STATIC_DRAW);
 
 
-            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 8, 0 as *const _);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 20, 0 as *const _);
             gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, 20, 8 as *const _);
+            gl::EnableVertexAttribArray(1);
 
             gl::BindVertexArray(vao);
             gl::DrawArrays(gl::TRIANGLES, 0, vertex_count);
             gl::DisableVertexAttribArray(0);
+            gl::DisableVertexAttribArray(1);
         }
     }
 }
 
-fn convert_state_to_vertices(display_state: Arc<RwLock<[[usize;64];32]>>) -> (Vec<f32>, i32) {
+fn convert_state_to_vertices(display_state: Arc<RwLock<Display>>) -> (Vec<f32>, i32) {
     let mut vertex_count = 0;
     //1. convert the display_state into an array of vertices and colors
-    //2. The max size the display_vector can have is: (64x32)x6 = 12_288
-    let mut display_vector = Vec::with_capacity(12_288);
+    //2. The max size the display_vector can have is: (128x64)x6x5 = 245_760
+    let mut display_vector = Vec::with_capacity(245_760);
     //The display vector looks like this:
-    // [color, v1.x, v1.y, v2.x, v2.y, ..., vn.x, nv.y]
+    // [v1.x, v1.y, v1.r, v1.g, v1.b, ..., vn.x, vn.y, vn.r, vn.g, vn.b]
     let read_display = display_state.read().unwrap();
-    for row in 0.. read_display.len() {
-        for col in 0.. read_display[row].len() {
-            if read_display[row][col] > 0 {
+    //3. The framebuffer is drawn as stored rather than at the active resolution, since
+    // legacy SUPER-CHIP scrolls can shift a low resolution image by half a pixel
+    let pixel_width = 2.0 / WIDTH as f32;
+    let pixel_height = 2.0 / HEIGHT as f32;
+    for (row, pixels) in read_display.pixels().iter().enumerate() {
+        for (col, pixel) in pixels.iter().enumerate() {
+            if *pixel > 0 {
                 vertex_count += 6;
 
-                let top_left = (-1.0 + col as f32/32.0, 1.0 - row as f32/16.0);
-                let bottom_left = (-1.0 + col as f32/32.0, 1.0 - (row+1) as f32/16.0);
-                let bottom_right = (-1.0 + (col+1) as f32/32.0, 1.0 - (row+1) as f32/16.0);
-                let top_right = (-1.0 + (col+1) as f32/32.0, 1.0 - row as f32/16.0);
-
-                display_vector.push(bottom_left.0);
-                display_vector.push(bottom_left.1);
-                display_vector.push(bottom_right.0);
-                display_vector.push(bottom_right.1);
-                display_vector.push(top_left.0);
-                display_vector.push(top_left.1);
-                display_vector.push(bottom_right.0);
-                display_vector.push(bottom_right.1);
-                display_vector.push(top_right.0);
-                display_vector.push(top_right.1);
-                display_vector.push(top_left.0);
-                display_vector.push(top_left.1);
+                let top_left = (-1.0 + col as f32 * pixel_width, 1.0 - row as f32 * pixel_height);
+                let bottom_left = (-1.0 + col as f32 * pixel_width, 1.0 - (row+1) as f32 * pixel_height);
+                let bottom_right = (-1.0 + (col+1) as f32 * pixel_width, 1.0 - (row+1) as f32 * pixel_height);
+                let top_right = (-1.0 + (col+1) as f32 * pixel_width, 1.0 - row as f32 * pixel_height);
+
+                let color = PALETTE[*pixel];
+                for vertex in [bottom_left, bottom_right, top_left, bottom_right, top_right, top_left] {
+                    display_vector.push(vertex.0);
+                    display_vector.push(vertex.1);
+                    display_vector.extend_from_slice(&color);
+                }
             }
         }
     }
@@ -182,12 +259,13 @@ unsafe fn create_shader_program() -> u32 {
 const VERTEX_SHADER_SOURCE: &str = "\
 #version 330 core
 layout (location=0) in vec2 vertexPosition;
+layout (location=1) in vec3 vertexColor;
 
 out vec3 fragmentColor;
 
 void main() {
     gl_Position = vec4(vertexPosition, 1.0, 1.0);
-    fragmentColor = (1.0, 1.0, 1.0);
+    fragmentColor = vertexColor;
 }
 ";
 
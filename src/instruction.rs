@@ -0,0 +1,392 @@
+use std::fmt;
+use crate::variant::Variant;
+
+// A decoded instruction. `x` and `y` are register indexes, `value` and `mask` the
+// 8-bit immediates and `address` the 12-bit one. Instructions that only exist in some
+// variants are only decoded for them, see `decode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    // 0NNN, machine code routine of the original interpreter, ignored
+    Syscall { address: u16 },
+    // 00E0
+    ClearScreen,
+    // 00EE
+    Return,
+    // 00CN (SUPER-CHIP)
+    ScrollDown { n: u8 },
+    // 00DN (XO-CHIP)
+    ScrollUp { n: u8 },
+    // 00FB (SUPER-CHIP)
+    ScrollRight,
+    // 00FC (SUPER-CHIP)
+    ScrollLeft,
+    // 00FD (SUPER-CHIP)
+    Exit,
+    // 00FE (SUPER-CHIP)
+    LowResolution,
+    // 00FF (SUPER-CHIP)
+    HighResolution,
+    // 1NNN
+    Jump { address: u16 },
+    // 2NNN
+    Call { address: u16 },
+    // 3XNN
+    SkipIfEqual { x: usize, value: u8 },
+    // 4XNN
+    SkipIfNotEqual { x: usize, value: u8 },
+    // 5XY0
+    SkipIfRegistersEqual { x: usize, y: usize },
+    // 5XY2 (XO-CHIP)
+    SaveRange { x: usize, y: usize },
+    // 5XY3 (XO-CHIP)
+    LoadRange { x: usize, y: usize },
+    // 6XNN
+    Load { x: usize, value: u8 },
+    // 7XNN
+    Add { x: usize, value: u8 },
+    // 8XY0
+    Move { x: usize, y: usize },
+    // 8XY1
+    Or { x: usize, y: usize },
+    // 8XY2
+    And { x: usize, y: usize },
+    // 8XY3
+    Xor { x: usize, y: usize },
+    // 8XY4
+    AddRegisters { x: usize, y: usize },
+    // 8XY5
+    Subtract { x: usize, y: usize },
+    // 8XY6
+    ShiftRight { x: usize, y: usize },
+    // 8XY7
+    SubtractReversed { x: usize, y: usize },
+    // 8XYE
+    ShiftLeft { x: usize, y: usize },
+    // 9XY0
+    SkipIfRegistersNotEqual { x: usize, y: usize },
+    // ANNN
+    LoadI { address: u16 },
+    // BNNN, read as BXNN with the jumping quirk
+    JumpOffset { address: u16 },
+    // CXNN
+    Random { x: usize, mask: u8 },
+    // DXYN
+    Draw { x: usize, y: usize, n: u8 },
+    // EX9E
+    SkipIfKey { x: usize },
+    // EXA1
+    SkipIfNotKey { x: usize },
+    // F000 NNNN (XO-CHIP), the address is in the 2 bytes following the opcode
+    LoadLongI,
+    // FN01 (XO-CHIP)
+    SelectPlanes { planes: u8 },
+    // F002 (XO-CHIP)
+    LoadAudioPattern,
+    // FX07
+    GetDelayTimer { x: usize },
+    // FX0A
+    WaitForKey { x: usize },
+    // FX15
+    SetDelayTimer { x: usize },
+    // FX18
+    SetSoundTimer { x: usize },
+    // FX1E
+    AddToI { x: usize },
+    // FX29
+    LoadFont { x: usize },
+    // FX30 (SUPER-CHIP)
+    LoadBigFont { x: usize },
+    // FX33
+    StoreBcd { x: usize },
+    // FX3A (XO-CHIP)
+    SetPitch { x: usize },
+    // FX55
+    StoreRegisters { x: usize },
+    // FX65
+    LoadRegisters { x: usize },
+    // FX75 (SUPER-CHIP)
+    SaveFlags { x: usize },
+    // FX85 (SUPER-CHIP)
+    LoadFlags { x: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown instruction {:#06x}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub fn decode(opcode: u16, variant: Variant) -> Result<Instruction, DecodeError> {
+    let x = ((opcode & 0x0F00) >> 8) as usize;
+    let y = ((opcode & 0x00F0) >> 4) as usize;
+    let n = (opcode & 0x000F) as u8;
+    let value = (opcode & 0x00FF) as u8;
+    let address = opcode & 0x0FFF;
+    let super_chip = variant.is_super_chip();
+    let xo_chip = variant.is_xo_chip();
+    let unknown = Err(DecodeError { opcode });
+
+    let instruction = match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => Instruction::ClearScreen,
+            0x00EE => Instruction::Return,
+            0x00C0..=0x00CF if super_chip => Instruction::ScrollDown { n },
+            0x00D0..=0x00DF if xo_chip => Instruction::ScrollUp { n },
+            0x00FB if super_chip => Instruction::ScrollRight,
+            0x00FC if super_chip => Instruction::ScrollLeft,
+            0x00FD if super_chip => Instruction::Exit,
+            0x00FE if super_chip => Instruction::LowResolution,
+            0x00FF if super_chip => Instruction::HighResolution,
+            _ => Instruction::Syscall { address },
+        },
+        0x1000 => Instruction::Jump { address },
+        0x2000 => Instruction::Call { address },
+        0x3000 => Instruction::SkipIfEqual { x, value },
+        0x4000 => Instruction::SkipIfNotEqual { x, value },
+        0x5000 => match n {
+            0x0 => Instruction::SkipIfRegistersEqual { x, y },
+            0x2 if xo_chip => Instruction::SaveRange { x, y },
+            0x3 if xo_chip => Instruction::LoadRange { x, y },
+            _ => return unknown,
+        },
+        0x6000 => Instruction::Load { x, value },
+        0x7000 => Instruction::Add { x, value },
+        0x8000 => match n {
+            0x0 => Instruction::Move { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddRegisters { x, y },
+            0x5 => Instruction::Subtract { x, y },
+            0x6 => Instruction::ShiftRight { x, y },
+            0x7 => Instruction::SubtractReversed { x, y },
+            0xE => Instruction::ShiftLeft { x, y },
+            _ => return unknown,
+        },
+        0x9000 => match n {
+            0x0 => Instruction::SkipIfRegistersNotEqual { x, y },
+            _ => return unknown,
+        },
+        0xA000 => Instruction::LoadI { address },
+        0xB000 => Instruction::JumpOffset { address },
+        0xC000 => Instruction::Random { x, mask: value },
+        0xD000 => Instruction::Draw { x, y, n },
+        0xE000 => match value {
+            0x9E => Instruction::SkipIfKey { x },
+            0xA1 => Instruction::SkipIfNotKey { x },
+            _ => return unknown,
+        },
+        _ => match value {
+            0x00 if xo_chip && x == 0 => Instruction::LoadLongI,
+            0x01 if xo_chip => Instruction::SelectPlanes { planes: x as u8 },
+            0x02 if xo_chip && x == 0 => Instruction::LoadAudioPattern,
+            0x07 => Instruction::GetDelayTimer { x },
+            0x0A => Instruction::WaitForKey { x },
+            0x15 => Instruction::SetDelayTimer { x },
+            0x18 => Instruction::SetSoundTimer { x },
+            0x1E => Instruction::AddToI { x },
+            0x29 => Instruction::LoadFont { x },
+            0x30 if super_chip => Instruction::LoadBigFont { x },
+            0x33 => Instruction::StoreBcd { x },
+            0x3A if xo_chip => Instruction::SetPitch { x },
+            0x55 => Instruction::StoreRegisters { x },
+            0x65 => Instruction::LoadRegisters { x },
+            0x75 if super_chip => Instruction::SaveFlags { x },
+            0x85 if super_chip => Instruction::LoadFlags { x },
+            _ => return unknown,
+        },
+    };
+    Ok(instruction)
+}
+
+impl Instruction {
+    // The opcode of the instruction, the inverse of `decode`
+    pub fn encode(&self) -> u16 {
+        let xy = |x: usize, y: usize| (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4;
+        let xnn = |x: usize, value: u8| (x as u16 & 0xF) << 8 | value as u16;
+        let fx = |x: usize, low: u16| 0xF000 | (x as u16 & 0xF) << 8 | low;
+        match *self {
+            Instruction::Syscall { address } => address & 0x0FFF,
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown { n } => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp { n } => 0x00D0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowResolution => 0x00FE,
+            Instruction::HighResolution => 0x00FF,
+            Instruction::Jump { address } => 0x1000 | (address & 0x0FFF),
+            Instruction::Call { address } => 0x2000 | (address & 0x0FFF),
+            Instruction::SkipIfEqual { x, value } => 0x3000 | xnn(x, value),
+            Instruction::SkipIfNotEqual { x, value } => 0x4000 | xnn(x, value),
+            Instruction::SkipIfRegistersEqual { x, y } => 0x5000 | xy(x, y),
+            Instruction::SaveRange { x, y } => 0x5002 | xy(x, y),
+            Instruction::LoadRange { x, y } => 0x5003 | xy(x, y),
+            Instruction::Load { x, value } => 0x6000 | xnn(x, value),
+            Instruction::Add { x, value } => 0x7000 | xnn(x, value),
+            Instruction::Move { x, y } => 0x8000 | xy(x, y),
+            Instruction::Or { x, y } => 0x8001 | xy(x, y),
+            Instruction::And { x, y } => 0x8002 | xy(x, y),
+            Instruction::Xor { x, y } => 0x8003 | xy(x, y),
+            Instruction::AddRegisters { x, y } => 0x8004 | xy(x, y),
+            Instruction::Subtract { x, y } => 0x8005 | xy(x, y),
+            Instruction::ShiftRight { x, y } => 0x8006 | xy(x, y),
+            Instruction::SubtractReversed { x, y } => 0x8007 | xy(x, y),
+            Instruction::ShiftLeft { x, y } => 0x800E | xy(x, y),
+            Instruction::SkipIfRegistersNotEqual { x, y } => 0x9000 | xy(x, y),
+            Instruction::LoadI { address } => 0xA000 | (address & 0x0FFF),
+            Instruction::JumpOffset { address } => 0xB000 | (address & 0x0FFF),
+            Instruction::Random { x, mask } => 0xC000 | xnn(x, mask),
+            Instruction::Draw { x, y, n } => 0xD000 | xy(x, y) | (n as u16 & 0xF),
+            Instruction::SkipIfKey { x } => 0xE09E | xnn(x, 0),
+            Instruction::SkipIfNotKey { x } => 0xE0A1 | xnn(x, 0),
+            Instruction::LoadLongI => 0xF000,
+            Instruction::SelectPlanes { planes } => fx(planes as usize, 0x01),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::GetDelayTimer { x } => fx(x, 0x07),
+            Instruction::WaitForKey { x } => fx(x, 0x0A),
+            Instruction::SetDelayTimer { x } => fx(x, 0x15),
+            Instruction::SetSoundTimer { x } => fx(x, 0x18),
+            Instruction::AddToI { x } => fx(x, 0x1E),
+            Instruction::LoadFont { x } => fx(x, 0x29),
+            Instruction::LoadBigFont { x } => fx(x, 0x30),
+            Instruction::StoreBcd { x } => fx(x, 0x33),
+            Instruction::SetPitch { x } => fx(x, 0x3A),
+            Instruction::StoreRegisters { x } => fx(x, 0x55),
+            Instruction::LoadRegisters { x } => fx(x, 0x65),
+            Instruction::SaveFlags { x } => fx(x, 0x75),
+            Instruction::LoadFlags { x } => fx(x, 0x85),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARIANTS: [Variant; 4] = [Variant::CosmacVip, Variant::SuperChip, Variant::SuperChipExtended, Variant::XoChip];
+
+    // Every instruction, with different registers and immediates up to the largest ones
+    const INSTRUCTIONS: &[Instruction] = &[
+        Instruction::Syscall { address: 0x123 },
+        Instruction::ClearScreen,
+        Instruction::Return,
+        Instruction::ScrollDown { n: 4 },
+        Instruction::ScrollUp { n: 7 },
+        Instruction::ScrollRight,
+        Instruction::ScrollLeft,
+        Instruction::Exit,
+        Instruction::LowResolution,
+        Instruction::HighResolution,
+        Instruction::Jump { address: 0x2A4 },
+        Instruction::Call { address: 0xFFE },
+        Instruction::SkipIfEqual { x: 1, value: 0x12 },
+        Instruction::SkipIfNotEqual { x: 2, value: 0xFF },
+        Instruction::SkipIfRegistersEqual { x: 3, y: 4 },
+        Instruction::SaveRange { x: 5, y: 6 },
+        Instruction::LoadRange { x: 7, y: 8 },
+        Instruction::Load { x: 9, value: 0x34 },
+        Instruction::Add { x: 0xA, value: 0x01 },
+        Instruction::Move { x: 0xB, y: 0xC },
+        Instruction::Or { x: 0xD, y: 0xE },
+        Instruction::And { x: 0xF, y: 0 },
+        Instruction::Xor { x: 0, y: 1 },
+        Instruction::AddRegisters { x: 1, y: 2 },
+        Instruction::Subtract { x: 2, y: 3 },
+        Instruction::ShiftRight { x: 3, y: 4 },
+        Instruction::SubtractReversed { x: 4, y: 5 },
+        Instruction::ShiftLeft { x: 5, y: 6 },
+        Instruction::SkipIfRegistersNotEqual { x: 6, y: 7 },
+        Instruction::LoadI { address: 0x800 },
+        Instruction::JumpOffset { address: 0x345 },
+        Instruction::Random { x: 7, mask: 0x0F },
+        Instruction::Draw { x: 8, y: 9, n: 0 },
+        Instruction::SkipIfKey { x: 9 },
+        Instruction::SkipIfNotKey { x: 0xA },
+        Instruction::LoadLongI,
+        Instruction::SelectPlanes { planes: 3 },
+        Instruction::LoadAudioPattern,
+        Instruction::GetDelayTimer { x: 0xB },
+        Instruction::WaitForKey { x: 0xC },
+        Instruction::SetDelayTimer { x: 0xD },
+        Instruction::SetSoundTimer { x: 0xE },
+        Instruction::AddToI { x: 0xF },
+        Instruction::LoadFont { x: 0 },
+        Instruction::LoadBigFont { x: 1 },
+        Instruction::StoreBcd { x: 2 },
+        Instruction::SetPitch { x: 3 },
+        Instruction::StoreRegisters { x: 4 },
+        Instruction::LoadRegisters { x: 5 },
+        Instruction::SaveFlags { x: 6 },
+        Instruction::LoadFlags { x: 7 },
+        Instruction::Draw { x: 0xF, y: 0xE, n: 0xF },
+        Instruction::Syscall { address: 0xFFF },
+    ];
+
+    #[test]
+    fn encoded_instructions_decode_back() {
+        // XO-CHIP decodes every instruction
+        for &instruction in INSTRUCTIONS {
+            assert_eq!(decode(instruction.encode(), Variant::XoChip), Ok(instruction), "{:04X}", instruction.encode());
+        }
+    }
+
+    #[test]
+    fn decoded_opcodes_encode_back() {
+        for variant in VARIANTS {
+            for opcode in 0..=0xFFFF {
+                if let Ok(instruction) = decode(opcode, variant) {
+                    assert_eq!(instruction.encode(), opcode, "{:?} on {}", instruction, variant);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn super_chip_instructions_need_a_super_chip_variant() {
+        for opcode in [0x00FF, 0x00FE, 0x00C5, 0x00FB, 0x00FC, 0x00FD] {
+            // Below 0x1000, the other opcodes are machine code routines
+            assert_eq!(decode(opcode, Variant::CosmacVip), Ok(Instruction::Syscall { address: opcode }));
+            for variant in [Variant::SuperChip, Variant::SuperChipExtended, Variant::XoChip] {
+                assert!(!matches!(decode(opcode, variant), Ok(Instruction::Syscall { .. }) | Err(_)), "{:04X} on {}", opcode, variant);
+            }
+        }
+        for opcode in [0xF130, 0xF275, 0xF385] {
+            assert_eq!(decode(opcode, Variant::CosmacVip), Err(DecodeError { opcode }));
+            for variant in [Variant::SuperChip, Variant::SuperChipExtended, Variant::XoChip] {
+                assert!(decode(opcode, variant).is_ok(), "{:04X} on {}", opcode, variant);
+            }
+        }
+    }
+
+    #[test]
+    fn xo_chip_instructions_need_xo_chip() {
+        for opcode in [0x5122, 0x5343, 0xF000, 0xF002, 0xF201, 0xF43A] {
+            for variant in [Variant::CosmacVip, Variant::SuperChip, Variant::SuperChipExtended] {
+                assert_eq!(decode(opcode, variant), Err(DecodeError { opcode }), "{}", variant);
+            }
+            assert!(decode(opcode, Variant::XoChip).is_ok(), "{:04X}", opcode);
+        }
+        // 00DN is a machine code routine before XO-CHIP
+        assert_eq!(decode(0x00D3, Variant::SuperChip), Ok(Instruction::Syscall { address: 0x0D3 }));
+        assert_eq!(decode(0x00D3, Variant::XoChip), Ok(Instruction::ScrollUp { n: 3 }));
+    }
+
+    #[test]
+    fn unknown_opcodes_are_reported() {
+        for opcode in [0x5121, 0x5124, 0x800F, 0x8128, 0x9121, 0xE19F, 0xE2A2, 0xF100, 0xF102, 0xF1FF] {
+            for variant in VARIANTS {
+                assert_eq!(decode(opcode, variant), Err(DecodeError { opcode }), "{:04X} on {}", opcode, variant);
+            }
+        }
+        assert_eq!(DecodeError { opcode: 0x800F }.to_string(), "Unknown instruction 0x800f");
+    }
+}
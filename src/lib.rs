@@ -0,0 +1,18 @@
+// The parts of the emulator that don't depend on the GUI: the machine itself, which can
+// be embedded without pulling in glfw, and the tools in src/bin
+pub mod assembler;
+pub mod audio;
+pub mod chip8;
+pub mod disassembler;
+pub mod display;
+pub mod dump;
+pub mod error;
+pub mod font;
+pub mod instruction;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod rpl;
+pub mod savestate;
+pub mod variant;
+pub mod wav;
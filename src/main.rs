@@ -1,12 +1,19 @@
 #![windows_subsystem = "windows"]
 
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::fs;
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 use rfd::FileDialog;
-use crate::emulator::{Emulator, Variant};
+use crate::emulator::{Emulator, Program};
 use crate::gui::run_gui;
+use rust_chip8_emulator::audio::{AudioSettings, Waveform};
+use rust_chip8_emulator::display::Display;
+use rust_chip8_emulator::error::EmulatorError;
+use rust_chip8_emulator::quirks::Quirks;
+use rust_chip8_emulator::variant::{calculate_hash, detect_variant};
+use rust_chip8_emulator::wav::WavRecorder;
 
 mod emulator;
 mod gui;
@@ -15,35 +22,54 @@ pub enum Status {
     Starting,
     Running,
     Paused,
+    // Going back one frame at a time while the rewind key is held
+    Rewinding,
     Stopped,
+    Halted(EmulatorError),
+}
+
+// Save state slots picked with F1-F9 in the GUI, saved or loaded by the emulator
+// thread between two frames
+pub enum StateRequest {
+    Save(u8),
+    Load(u8),
 }
 
 pub fn main() {
     // Initializing the shared state
-    let display_state: Arc<RwLock<[[usize;64];32]>> = Arc::new(RwLock::new([[0;64];32]));
-    let memory: Arc<RwLock<Vec<u8>>> = Arc::new(RwLock::new(Vec::with_capacity(4096)));
+    let display_state: Arc<RwLock<Display>> = Arc::new(RwLock::new(Display::new()));
     let status: Arc<RwLock<Status>> = Arc::new(RwLock::new(Status::Starting));
     let keys: Arc<RwLock<[bool;16]>> = Arc::new(RwLock::new([false;16]));
+    let state_request: Arc<RwLock<Option<StateRequest>>> = Arc::new(RwLock::new(None));
 
-    let mut is_running = false;
-    let mut variant = Variant::CosmacVip;
-    match load_game(memory.clone()) {
-        Some(v) => {
+    if let Some(program) = load_game() {
+        {
             let mut status_write = status.write().unwrap();
             *status_write = Status::Running;
-            is_running = true;
-            variant = v;
-        },
-        _ => {}
-    }
-
-    if is_running {
-        let mut emulator = Emulator::new(status.clone(), memory.clone(), display_state.clone(), keys.clone(), variant);
+        }
+        let seed = parse_seed().unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
+        });
+        println!("Random seed: {} (pass --seed {} to reproduce this run)", seed, seed);
+        let audio_settings = parse_audio_settings();
+        // `--record <file>` saves the sound of the session to a WAV file
+        let recorder = option_value("--record").and_then(|path| {
+            WavRecorder::create(Path::new(&path), &audio_settings)
+                .inspect_err(|error| eprintln!("Can't record to {}: {}", path, error))
+                .ok()
+        });
+        let mut emulator = match Emulator::new(status.clone(), display_state.clone(), keys.clone(), state_request.clone(), program, seed, audio_settings, recorder) {
+            Ok(emulator) => emulator,
+            Err(error) => {
+                eprintln!("{}", error);
+                return;
+            }
+        };
         let emulator_handle = thread::spawn(move || emulator.run());
         let display_state_copy = display_state.clone();
         let pressed_key_gui_copy = keys.clone();
         let status_clone = status.clone();
-        let gui_handle = thread::spawn(|| run_gui(display_state_copy, pressed_key_gui_copy, status_clone));
+        let gui_handle = thread::spawn(|| run_gui(display_state_copy, pressed_key_gui_copy, status_clone, state_request));
         gui_handle.join().unwrap();
         {
             let mut status_write = status.write().unwrap();
@@ -56,62 +82,64 @@ pub fn main() {
 
 }
 
-fn load_game(memory: Arc<RwLock<Vec<u8>>>) -> Option<Variant>{
-    let files = FileDialog::new()
-        .add_filter("Chip 8", &["ch8"])
-        .set_directory("/")
-        .set_title("Choose a Chip 8 Program")
-        .pick_file();
-
-    if files.is_none() {return None};
+// The value following `name` on the command line
+fn option_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
 
-    let file = File::open(files.unwrap()).unwrap();
-    let buf = BufReader::new(file);
-    let mut ram = memory.write().unwrap();
-    *ram = vec![0;4096];
-    for i in 0..SPRITES.len() {
-        ram[i] = SPRITES[i];
+// Reads the seed for the random number generator from `--seed <number>`,
+// the number being either decimal or hexadecimal with a 0x prefix
+fn parse_seed() -> Option<u64> {
+    let value = option_value("--seed")?;
+    let seed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse()
+    };
+    match seed {
+        Ok(seed) => Some(seed),
+        Err(_) => {
+            eprintln!("Ignoring invalid seed: {}", value);
+            None
+        }
     }
-    let mut index = 0x200;
-    buf.bytes().for_each(|i| {
-        let byte = i.unwrap();
-        ram[index] = byte;
-        index += 1;
-    });
-
-    drop(ram);
-    Some(map_hash_to_variant(memory))
 }
 
-const SPRITES: [u8;80] = [0xF0,0x90,0x90,0x90,0xF0, 0x20,0x60,0x20,0x20,0x70, 0xF0,0x10,0xF0,0x80,0xF0, 0xF0,0x10,0xF0,0x10,0xF0, 0x90,0x90,0xF0,0x10,0x10, 0xF0,0x80,0xF0,0x10,0xF0, 0xF0,0x80,0xF0,0x90,0xF0, 0xF0,0x10,0x20,0x40,0x40, 0xF0,0x90,0xF0,0x90,0xF0, 0xF0,0x90,0xF0,0x10,0xF0, 0xF0,0x90,0xF0,0x90,0x90, 0xE0,0x90,0xE0,0x90,0xE0, 0xF0,0x80,0x80,0x80,0xF0, 0xE0,0x90,0x90,0x90,0xE0, 0xF0,0x80,0xF0,0x80,0xF0, 0xF0,0x80,0xF0,0x80,0x80];
-fn map_hash_to_variant(memory: Arc<RwLock<Vec<u8>>>) -> Variant {
-    match calculate_hash(memory) {
-        0x2d0e7c46 => {Variant::SuperChip}, // Space Invaders
-        0x721983d5 => {Variant::SuperChip}, // Astro Dodge
-        0xecc2538b => {Variant::SuperChip}, // Blinky
-        0xb59f8fa9 => {Variant::SuperChip}, // Blinky Alt
-        0x80661d05 => {Variant::SuperChip}, // Blitz -> This one is broken, will have to fix
-        0x4acbee72 => {Variant::SuperChip}, // Bowling
-        0x28132140 => {Variant::SuperChip}, // Breakout (Winter)
-        _ => {Variant::CosmacVip}
+// Reads the waveform of the buzzer from `--waveform <name>` and its volume from
+// `--volume <percent>`, keeping the defaults for anything missing or invalid
+fn parse_audio_settings() -> AudioSettings {
+    let mut settings = AudioSettings::default();
+    if let Some(value) = option_value("--waveform") {
+        match value.parse::<Waveform>() {
+            Ok(waveform) => settings.waveform = waveform,
+            Err(error) => eprintln!("{}", error)
+        }
+    }
+    if let Some(value) = option_value("--volume") {
+        match value.parse::<u8>() {
+            Ok(percent) if percent <= 100 => settings.volume = percent as f32 / 100.0,
+            _ => eprintln!("Ignoring invalid volume: {}, expected a percentage", value)
+        }
     }
+    settings
 }
 
-fn calculate_hash(memory: Arc<RwLock<Vec<u8>>>) -> u64 {
-    let read_memory = memory.read().unwrap();
-    let mut assembled_instructions = [0u64;4];
-    let mem_index = 0x200;
-    let mut counter = 0;
-    for i in 0.. 32 {
-        if i != 0 && i % 8 == 0 {counter += 1;}
-        assembled_instructions[counter] |= (read_memory[mem_index + i] as u64) << (8 *(i % 4));
-    }
+fn load_game() -> Option<Program>{
+    let files = FileDialog::new()
+        .add_filter("Chip 8", &["ch8", "xo8"])
+        .set_directory("/")
+        .set_title("Choose a Chip 8 Program")
+        .pick_file();
 
-    let mut result = 0;
-    for n in assembled_instructions {
-        result ^= n;
-    }
+    if files.is_none() {return None};
 
-    println!("Hash: {:#x}", result);
-    result
-}
\ No newline at end of file
+    let path = files.unwrap();
+    let rom = fs::read(&path).unwrap();
+    let hash = calculate_hash(&rom);
+    println!("Hash: {:#x}", hash);
+    let variant = detect_variant(&path, &rom);
+    let mut quirks = Quirks::for_variant(&variant);
+    for warning in quirks.load_overrides(&path) {
+        eprintln!("{}", warning);
+    }
+    Some(Program {rom, variant, quirks, hash})
+}
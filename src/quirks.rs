@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use crate::variant::Variant;
+
+// Behaviours that differ between CHIP-8 interpreters. Each variant comes with its own
+// defaults, which can be overridden per program with a `.quirks` file placed next to it.
+// The file holds one `name = true|false` line per quirk to override, `#` starts a comment.
+pub struct Quirks {
+    // 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // 8XY6 and 8XYE shift VX in place instead of shifting VY into VX
+    pub shifting: bool,
+    // FX55 and FX65 leave I pointing after the last register stored or loaded
+    pub memory_increment: bool,
+    // BNNN is read as BXNN and jumps to XNN + VX instead of NNN + V0
+    pub jumping: bool,
+    // Sprites are cut at the edges of the screen instead of wrapping around
+    pub clipping: bool,
+    // DXYN ends the current 60Hz frame, so the program resumes on the next display refresh
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    pub fn for_variant(variant: &Variant) -> Quirks {
+        match variant {
+            // The VIP's DXYN waits for the display interrupt, which limits it to 60 draws per second
+            Variant::CosmacVip => Quirks {
+                vf_reset: true,
+                shifting: false,
+                memory_increment: true,
+                jumping: false,
+                clipping: true,
+                display_wait: true,
+            },
+            // SUPER-CHIP inherited BXNN from CHIP-48, which jump-table driven games like Blitz rely on
+            Variant::SuperChip | Variant::SuperChipExtended => Quirks {
+                vf_reset: false,
+                shifting: true,
+                memory_increment: false,
+                jumping: true,
+                clipping: true,
+                display_wait: false,
+            },
+            // Octo wraps sprites around the screen edges
+            Variant::XoChip => Quirks {
+                vf_reset: false,
+                shifting: false,
+                memory_increment: true,
+                jumping: false,
+                clipping: false,
+                display_wait: false,
+            },
+        }
+    }
+
+    // Applies the overrides from the program's `.quirks` file, if there is one, and
+    // returns a warning for every line that was ignored
+    pub fn load_overrides(&mut self, program_path: &Path) -> Vec<String> {
+        match fs::read_to_string(program_path.with_extension("quirks")) {
+            Ok(config) => self.apply_overrides(&config),
+            Err(_) => Vec::new()
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        for quirk in [self.vf_reset, self.shifting, self.memory_increment, self.jumping, self.clipping, self.display_wait] {
+            writer.write_bool(quirk);
+        }
+    }
+
+    pub fn load_state(reader: &mut StateReader) -> Result<Quirks, SaveStateError> {
+        Ok(Quirks {
+            vf_reset: reader.read_bool()?,
+            shifting: reader.read_bool()?,
+            memory_increment: reader.read_bool()?,
+            jumping: reader.read_bool()?,
+            clipping: reader.read_bool()?,
+            display_wait: reader.read_bool()?,
+        })
+    }
+
+    pub fn apply_overrides(&mut self, config: &str) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for line in config.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                warnings.push(format!("Ignoring malformed quirk: {}", line));
+                continue;
+            };
+            let value = match value.trim() {
+                "true" => true,
+                "false" => false,
+                other => {
+                    warnings.push(format!("Ignoring quirk {} with value {}, expected true or false", name.trim(), other));
+                    continue;
+                }
+            };
+            match name.trim() {
+                "vf_reset" => self.vf_reset = value,
+                "shifting" => self.shifting = value,
+                "memory_increment" => self.memory_increment = value,
+                "jumping" => self.jumping = value,
+                "clipping" => self.clipping = value,
+                "display_wait" => self.display_wait = value,
+                other => warnings.push(format!("Ignoring unknown quirk: {}", other)),
+            }
+        }
+        warnings
+    }
+}
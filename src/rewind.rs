@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+// 60 seconds at 60 frames per second
+pub const DEFAULT_REWIND_FRAMES: usize = 60 * 60;
+// Past this, the oldest frames are dropped even if fewer than the maximum are kept.
+// Frames usually take a few dozen bytes, this only matters for programs rewriting
+// most of their memory every frame.
+const MAX_REWIND_BYTES: usize = 64 * 1024 * 1024;
+
+// The last frames of the program, to go back in time one frame after another.
+// Only the latest state (see `Chip8::save_state`) is kept whole. Each older frame is
+// stored as its difference with the frame after it, XORed byte by byte and with the
+// runs of zeros compressed: since a frame changes little of the memory and the
+// display, most frames take a few dozen bytes.
+pub struct Rewind {
+    latest: Option<Vec<u8>>,
+    // The oldest frame first
+    deltas: VecDeque<Vec<u8>>,
+    max_frames: usize,
+    bytes: usize
+}
+
+impl Rewind {
+    pub fn new(max_frames: usize) -> Rewind {
+        Rewind {
+            latest: None,
+            deltas: VecDeque::new(),
+            max_frames,
+            bytes: 0
+        }
+    }
+
+    // Records the state of the machine after a frame
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            let delta = encode_delta(&previous, &state);
+            self.bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(state);
+        while self.deltas.len() > self.max_frames || self.bytes > MAX_REWIND_BYTES {
+            let Some(oldest) = self.deltas.pop_front() else {
+                break;
+            };
+            self.bytes -= oldest.len();
+        }
+    }
+
+    // Goes back one frame and returns its state, to load into the machine. Returns None
+    // once the oldest frame is reached.
+    pub fn step_back(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.bytes -= delta.len();
+        let latest = self.latest.as_ref()?;
+        let previous = decode_delta(latest, &delta);
+        self.latest = Some(previous.clone());
+        Some(previous)
+    }
+
+    // The number of frames it can go back
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    // The memory taken by the frames, apart from the latest one
+    pub fn size_in_bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+// Encodes `previous` as its difference with `next`: the length of `previous`, then
+// pairs of a number of identical bytes and a number of differing ones, followed by the
+// XOR of the differing ones. States may differ in length, the shorter one is read as
+// padded with zeros.
+fn encode_delta(previous: &[u8], next: &[u8]) -> Vec<u8> {
+    let length = previous.len().max(next.len());
+    let mut xor = previous.to_vec();
+    xor.resize(length, 0);
+    for (byte, next_byte) in xor.iter_mut().zip(next) {
+        *byte ^= next_byte;
+    }
+    let mut delta = Vec::new();
+    write_length(&mut delta, previous.len());
+    let mut index = 0;
+    while index < length {
+        let same = xor[index..].iter().position(|byte| *byte != 0).unwrap_or(length - index);
+        let different_start = index + same;
+        let different = xor[different_start..].iter().position(|byte| *byte == 0).unwrap_or(length - different_start);
+        index = different_start + different;
+        write_length(&mut delta, same);
+        write_length(&mut delta, different);
+        delta.extend_from_slice(&xor[different_start..index]);
+    }
+    delta
+}
+
+fn decode_delta(next: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let previous_length = read_length(delta, &mut position);
+    let mut previous = next.to_vec();
+    previous.resize(previous.len().max(previous_length), 0);
+    let mut index = 0;
+    while position < delta.len() {
+        index += read_length(delta, &mut position);
+        let different = read_length(delta, &mut position);
+        for byte in &mut previous[index..index + different] {
+            *byte ^= delta[position];
+            position += 1;
+        }
+        index += different;
+    }
+    previous.truncate(previous_length);
+    previous
+}
+
+// Lengths are written 7 bits per byte, the high bit telling whether more bytes follow
+fn write_length(bytes: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        bytes.push((length & 0x7F) as u8 | 0x80);
+        length >>= 7;
+    }
+    bytes.push(length as u8);
+}
+
+fn read_length(bytes: &[u8], position: &mut usize) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*position];
+        *position += 1;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return length;
+        }
+        shift += 7;
+    }
+}
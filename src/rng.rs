@@ -0,0 +1,34 @@
+// Random number generator used by CXNN (xorshift64*). Seeding it with the same value
+// makes a program behave the same way across runs, which helps reproducing bugs.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The seed is scrambled with a splitmix64 step so that close seeds give unrelated
+        // sequences, and so that the state is never 0, which xorshift can't leave
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng { state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z } }
+    }
+
+    // The internal state, which save states keep to carry on with the same sequence
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn from_state(state: u64) -> Rng {
+        Rng { state: if state == 0 { 0x9E37_79B9_7F4A_7C15 } else { state } }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        // The high bits of xorshift64* are the most random ones
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
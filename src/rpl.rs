@@ -0,0 +1,42 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// SUPER-CHIP RPL user flags (FX75/FX85) are kept in a small file per program,
+// named after the program's hash, so that saved high scores survive restarts.
+pub const RPL_FLAG_COUNT: usize = 16;
+
+pub fn load_flags(rom_hash: u64) -> [u8; RPL_FLAG_COUNT] {
+    let mut flags = [0; RPL_FLAG_COUNT];
+    if let Ok(bytes) = fs::read(flags_path(rom_hash)) {
+        for (flag, byte) in flags.iter_mut().zip(bytes) {
+            *flag = byte;
+        }
+    }
+    flags
+}
+
+pub fn save_flags(rom_hash: u64, flags: &[u8; RPL_FLAG_COUNT]) -> io::Result<()> {
+    let path = flags_path(rom_hash);
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(&path, flags)
+}
+
+// The flags live in ~/.rust-chip8-emulator/rpl/
+fn flags_path(rom_hash: u64) -> PathBuf {
+    data_directory()
+        .join("rpl")
+        .join(format!("{:016x}.rpl", rom_hash))
+}
+
+// ~/.rust-chip8-emulator, or a directory of that name in the working directory when no
+// home directory is available
+pub(crate) fn data_directory() -> PathBuf {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    home.join(".rust-chip8-emulator")
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use crate::rpl::data_directory;
+
+// Save states start with these bytes, followed by the version of the format, the hash
+// of the program they were made with and the state of each part of the machine, see
+// `Chip8::save_state`. Numbers are little-endian.
+const MAGIC: &[u8; 4] = b"C8SS";
+// Increased whenever the layout changes. Older save states are refused rather than
+// misread.
+pub const SAVE_STATE_VERSION: u8 = 3;
+
+// Why a save state couldn't be loaded. The machine is left untouched in every case.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaveStateError {
+    NotASaveState,
+    UnsupportedVersion { version: u8 },
+    // The save state was made with another program
+    WrongProgram { expected: u64, found: u64 },
+    WrongVariant,
+    // The file ends early or holds impossible values
+    Corrupted
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "Not a save state"),
+            SaveStateError::UnsupportedVersion { version } => write!(
+                f, "Save state version {} is not supported, expected version {}", version, SAVE_STATE_VERSION
+            ),
+            SaveStateError::WrongProgram { expected, found } => write!(
+                f, "The save state is for the program with hash {:016x}, not {:016x}", found, expected
+            ),
+            SaveStateError::WrongVariant => write!(f, "The save state is for another variant"),
+            SaveStateError::Corrupted => write!(f, "The save state is corrupted")
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+// Appends the parts of the machine to a save state
+pub struct StateWriter {
+    bytes: Vec<u8>
+}
+
+impl StateWriter {
+    pub fn new(rom_hash: u64) -> StateWriter {
+        let mut writer = StateWriter { bytes: Vec::new() };
+        writer.write_bytes(MAGIC);
+        writer.write_u8(SAVE_STATE_VERSION);
+        writer.write_u64(rom_hash);
+        writer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+// Reads the parts of the machine back from a save state, in the order they were written
+pub struct StateReader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> StateReader<'a> {
+    // Checks the header against the program currently loaded
+    pub fn new(bytes: &'a [u8], rom_hash: u64) -> Result<StateReader<'a>, SaveStateError> {
+        let mut reader = StateReader { bytes };
+        if reader.read_bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let version = reader.read_u8()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion { version });
+        }
+        let found = reader.read_u64()?;
+        if found != rom_hash {
+            return Err(SaveStateError::WrongProgram { expected: rom_hash, found });
+        }
+        Ok(reader)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupted)
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        if length > self.bytes.len() {
+            return Err(SaveStateError::Corrupted);
+        }
+        let (bytes, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    // Anything left after the last part means the save state was misread
+    pub fn finish(self) -> Result<(), SaveStateError> {
+        if self.bytes.is_empty() { Ok(()) } else { Err(SaveStateError::Corrupted) }
+    }
+}
+
+// Identifies the program a save state belongs to. Unlike `calculate_hash`, which only
+// looks at the start of the program, it covers every byte (64-bit FNV-1a).
+pub fn hash_rom(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3))
+}
+
+// Save states are kept in ~/.rust-chip8-emulator/states/, one file per program and slot
+pub fn slot_path(rom_hash: u64, slot: u8) -> PathBuf {
+    data_directory().join("states").join(format!("{:016x}.{}.state", rom_hash, slot))
+}
+
+pub fn save_slot(rom_hash: u64, slot: u8, state: &[u8]) -> io::Result<PathBuf> {
+    let path = slot_path(rom_hash, slot);
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(&path, state)?;
+    Ok(path)
+}
+
+pub fn load_slot(rom_hash: u64, slot: u8) -> io::Result<Vec<u8>> {
+    fs::read(slot_path(rom_hash, slot))
+}
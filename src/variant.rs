@@ -0,0 +1,98 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use crate::chip8::PROGRAM_START;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    CosmacVip,
+    SuperChip,
+    SuperChipExtended,
+    XoChip
+}
+
+impl Variant {
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Variant::XoChip => 0x10000,
+            _ => 0x1000
+        }
+    }
+
+    // XO-CHIP is a superset of SUPER-CHIP, so it supports all of its instructions as well
+    pub fn is_super_chip(&self) -> bool {
+        matches!(self, Variant::SuperChip | Variant::SuperChipExtended | Variant::XoChip)
+    }
+
+    pub fn is_xo_chip(&self) -> bool {
+        matches!(self, Variant::XoChip)
+    }
+}
+
+// Names used to pick a variant on the command line
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Variant, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "chip8" | "vip" => Ok(Variant::CosmacVip),
+            "schip" => Ok(Variant::SuperChip),
+            "schip-extended" => Ok(Variant::SuperChipExtended),
+            "xochip" => Ok(Variant::XoChip),
+            _ => Err(format!("Unknown variant {}, expected chip8, schip, schip-extended or xochip", name))
+        }
+    }
+}
+
+// The name a variant is picked with, which FromStr reads back
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Variant::CosmacVip => "chip8",
+            Variant::SuperChip => "schip",
+            Variant::SuperChipExtended => "schip-extended",
+            Variant::XoChip => "xochip"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Guesses the variant a program was written for
+pub fn detect_variant(path: &Path, rom: &[u8]) -> Variant {
+    // Octo exports XO-CHIP programs as .xo8, and programs that don't fit
+    // in 4 KiB of memory can only be XO-CHIP ones
+    let is_xo_chip = path.extension().is_some_and(|extension| extension == "xo8")
+        || PROGRAM_START + rom.len() > Variant::CosmacVip.memory_size();
+    if is_xo_chip {Variant::XoChip} else {map_hash_to_variant(calculate_hash(rom))}
+}
+
+fn map_hash_to_variant(hash: u64) -> Variant {
+    match hash {
+        0x2d0e7c46 => {Variant::SuperChip}, // Space Invaders
+        0x721983d5 => {Variant::SuperChip}, // Astro Dodge
+        0xecc2538b => {Variant::SuperChip}, // Blinky
+        0xb59f8fa9 => {Variant::SuperChip}, // Blinky Alt
+        0x80661d05 => {Variant::SuperChip}, // Blitz
+        0x4acbee72 => {Variant::SuperChip}, // Bowling
+        0x28132140 => {Variant::SuperChip}, // Breakout (Winter)
+        _ => {Variant::CosmacVip}
+    }
+}
+
+// Hashes the first 32 bytes of the program
+pub fn calculate_hash(rom: &[u8]) -> u64 {
+    let mut assembled_instructions = [0u64;4];
+    let mut counter = 0;
+    for i in 0.. 32 {
+        if i != 0 && i % 8 == 0 {counter += 1;}
+        let byte = rom.get(i).copied().unwrap_or(0);
+        assembled_instructions[counter] |= (byte as u64) << (8 *(i % 4));
+    }
+
+    let mut result = 0;
+    for n in assembled_instructions {
+        result ^= n;
+    }
+
+    result
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::audio::{AudioSettings, Sound, Synth};
+
+// Sample rate of the recordings, in mono and 16 bits
+pub const RECORDING_SAMPLE_RATE: u32 = 44100;
+// Size of the RIFF and fmt headers, and of the header of the data chunk
+const HEADER_SIZE: u32 = 44;
+// The RIFF header holds the size of the rest of the file in 32 bits, which limits
+// recordings to a bit less than 4 GiB, about 13 hours and a half
+const MAX_SAMPLES: u64 = (u32::MAX - (HEADER_SIZE - 8)) as u64 / 2;
+
+// Records the sound of the machine to a WAV file. Every emulated frame adds exactly
+// 1/60th of a second of audio, however long it took to run, so a recording made
+// without a window or while the emulator is running behind sounds as the program
+// intended.
+pub struct WavRecorder<W: Write + Seek> {
+    writer: W,
+    synth: Synth,
+    frames: u64,
+    samples: u64
+}
+
+impl WavRecorder<BufWriter<File>> {
+    pub fn create(path: &Path, settings: &AudioSettings) -> io::Result<WavRecorder<BufWriter<File>>> {
+        WavRecorder::new(BufWriter::new(File::create(path)?), settings)
+    }
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    // Writes the header right away, its sizes are filled in by `finish`
+    pub fn new(mut writer: W, settings: &AudioSettings) -> io::Result<WavRecorder<W>> {
+        write_header(&mut writer, 0)?;
+        Ok(WavRecorder {
+            writer,
+            synth: Synth::new(settings, RECORDING_SAMPLE_RATE),
+            frames: 0,
+            samples: 0
+        })
+    }
+
+    // Adds one 60Hz frame playing `sound`, usually `Chip8::sound` after `run_frame`.
+    // The sample rate isn't a multiple of 60, so frames are rounded to whole samples
+    // in a way that adds up to the exact duration.
+    // Once the recording is as long as a WAV file allows, the frames are refused; the
+    // recording can still be finished.
+    pub fn record_frame(&mut self, sound: &Sound) -> io::Result<()> {
+        let end = (self.frames + 1) * RECORDING_SAMPLE_RATE as u64 / 60;
+        if end > MAX_SAMPLES {
+            return Err(io::Error::other("The recording is longer than a WAV file can hold"));
+        }
+        self.synth.play(sound);
+        self.frames += 1;
+        while self.samples < end {
+            let sample = (self.synth.next_sample().clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+            self.samples += 1;
+        }
+        Ok(())
+    }
+
+    // Completes the header with the length of the recording and returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples.checked_mul(2)
+            .and_then(|size| u32::try_from(size).ok())
+            .ok_or_else(|| io::Error::other("The recording is longer than a WAV file can hold"))?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, data_size)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// The canonical 44-byte header of a PCM WAV file
+fn write_header(writer: &mut impl Write, data_size: u32) -> io::Result<()> {
+    let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(HEADER_SIZE - 8 + data_size).to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    header.extend_from_slice(&RECORDING_SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(RECORDING_SAMPLE_RATE * 2).to_le_bytes()); // Bytes per second
+    header.extend_from_slice(&2u16.to_le_bytes()); // Bytes per sample
+    header.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+    writer.write_all(&header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn recording_stops_at_the_size_limit_of_wav_files() {
+        let mut recorder = WavRecorder::new(Cursor::new(Vec::new()), &AudioSettings::default()).unwrap();
+        // Pretends that the recording is almost 4 GiB long, 2 frames from the limit
+        recorder.frames = MAX_SAMPLES * 60 / RECORDING_SAMPLE_RATE as u64 - 2;
+        recorder.samples = recorder.frames * RECORDING_SAMPLE_RATE as u64 / 60;
+        let sound = Sound::default();
+        recorder.record_frame(&sound).unwrap();
+        recorder.record_frame(&sound).unwrap();
+        assert!(recorder.record_frame(&sound).is_err());
+        assert!(recorder.samples <= MAX_SAMPLES);
+
+        let wav = recorder.finish().unwrap().into_inner();
+        let riff_size = u32::from_le_bytes(wav[4..8].try_into().unwrap());
+        let data_size = u32::from_le_bytes(wav[40..44].try_into().unwrap());
+        assert_eq!(riff_size, HEADER_SIZE - 8 + data_size);
+        assert!(data_size > u32::MAX - 8000);
+    }
+}
@@ -0,0 +1,217 @@
+// Assembles small programs in both syntaxes, checks the ROM and the symbol file, that
+// the disassembler gives the sources back, and the errors for invalid sources.
+
+use rust_chip8_emulator::assembler::{assemble, AssemblyError};
+use rust_chip8_emulator::disassembler::{disassemble, Syntax};
+use rust_chip8_emulator::variant::Variant;
+
+// Uses labels before defining them, for both 12 and 16-bit addresses
+const OCTO_PROGRAM: &str = ": main
+    i := long sprite
+    :call draw
+    : loop
+    jump loop
+    : draw
+    sprite v0 v1 1
+    return
+    : sprite
+    0xFF";
+
+const COWGOD_PROGRAM: &str = "main:
+    LD I, LONG sprite
+    CALL draw
+loop:
+    JP loop
+draw:
+    DRW V0, V1, 1
+    RET
+sprite:
+    DB #FF";
+
+const ROM: [u8; 13] = [0xF0, 0x00, 0x02, 0x0C, 0x22, 0x08, 0x12, 0x06, 0xD0, 0x11, 0x00, 0xEE, 0xFF];
+
+// Every instruction but 0NNN and BNNN, which aren't listed as code, written the way the
+// disassembler lists them and with the labels it generates
+const OCTO_LISTING: &str = "clear
+hires
+lores
+scroll-down 3
+scroll-up 2
+scroll-right
+scroll-left
+:call sub_0264
+v0 := 0x12
+v1 += 0xFF
+v2 := v3
+v4 |= v5
+v6 &= v7
+v8 ^= v9
+va += vb
+vc -= vd
+ve >>= vf
+v0 =- v1
+v2 <<= v3
+if v4 != 0x05 then
+v6 := random 0x0F
+if v7 == 0x08 then
+v9 := delay
+if va != vb then
+vc := key
+if vd == ve then
+delay := vf
+if v0 -key then
+buzzer := v1
+if v2 key then
+i := data_0268
+i := long data_0268
+i += v3
+i := hex v4
+i := bighex v5
+bcd v6
+save v7
+load v8
+save v9 - va
+load vb - vc
+saveflags vd
+loadflags ve
+plane 3
+audio
+pitch := vf
+if v3 == 0x00 then
+exit
+sprite v0 v1 15
+: label_0262
+jump label_0262
+: sub_0264
+sprite v2 v3 0
+return
+: data_0268
+0x01 0x02";
+
+const COWGOD_LISTING: &str = "CLS
+HIGH
+LOW
+SCD 3
+SCU 2
+SCR
+SCL
+CALL sub_0264
+LD V0, #12
+ADD V1, #FF
+LD V2, V3
+OR V4, V5
+AND V6, V7
+XOR V8, V9
+ADD VA, VB
+SUB VC, VD
+SHR VE, VF
+SUBN V0, V1
+SHL V2, V3
+SE V4, #05
+RND V6, #0F
+SNE V7, #08
+LD V9, DT
+SE VA, VB
+LD VC, K
+SNE VD, VE
+LD DT, VF
+SKP V0
+LD ST, V1
+SKNP V2
+LD I, data_0268
+LD I, LONG data_0268
+ADD I, V3
+LD F, V4
+LD HF, V5
+LD B, V6
+LD [I], V7
+LD V8, [I]
+SAVE V9 - VA
+LOAD VB - VC
+LD R, VD
+LD VE, R
+PLANE 3
+AUDIO
+PITCH VF
+SNE V3, #00
+EXIT
+DRW V0, V1, 15
+label_0262:
+JP label_0262
+sub_0264:
+DRW V2, V3, 0
+RET
+data_0268:
+DB #01, #02";
+
+#[test]
+fn labels_are_resolved_after_the_whole_source() {
+    let assembly = assemble(OCTO_PROGRAM, Syntax::Octo).unwrap();
+    assert_eq!(assembly.rom, ROM);
+    assert_eq!(assembly.symbol_file(), "0x0200 main\n0x0206 loop\n0x0208 draw\n0x020c sprite\n");
+    assert_eq!(assemble(COWGOD_PROGRAM, Syntax::Cowgod).unwrap().rom, ROM);
+}
+
+#[test]
+fn disassembling_gives_the_source_back() {
+    for (source, syntax) in [(OCTO_LISTING, Syntax::Octo), (COWGOD_LISTING, Syntax::Cowgod)] {
+        let rom = assemble(source, syntax).unwrap().rom;
+        let listing = disassemble(&rom, Variant::XoChip, syntax);
+        // Drops the address and the bytes in front of the instructions
+        let lines: Vec<&str> = listing.lines()
+            .map(|line| if line.starts_with(':') || line.ends_with(':') { line } else { &line[19..] })
+            .collect();
+        assert_eq!(lines.join("\n"), source);
+    }
+}
+
+#[test]
+fn computed_jumps_take_any_register_in_cowgod_syntax() {
+    // JP VX, NN is BXNN, as the disassembler lists it for the variants with the jumping quirk
+    assert_eq!(assemble("JP V0, #30A\nJP V3, #0A", Syntax::Cowgod).unwrap().rom, [0xB3, 0x0A, 0xB3, 0x0A]);
+}
+
+fn error(source: &str, syntax: Syntax) -> AssemblyError {
+    match assemble(source, syntax) {
+        Ok(_) => panic!("{} assembled", source),
+        Err(error) => error
+    }
+}
+
+#[test]
+fn unknown_statements_are_reported() {
+    assert_eq!(error("clear\n@", Syntax::Octo).to_string(), "Line 2: Unknown statement @");
+    assert_eq!(error("CLS\nFROB V0", Syntax::Cowgod).to_string(), "Line 2: Unknown instruction FROB");
+    // Bare names are calls in Octo
+    assert_eq!(error("frobnicate", Syntax::Octo).to_string(), "Line 1: Unknown label frobnicate");
+    assert_eq!(error("JP nowhere", Syntax::Cowgod).to_string(), "Line 1: Unknown label nowhere");
+    assert_eq!(error(": a\n: a", Syntax::Octo).to_string(), "Line 2: Label a is defined twice");
+}
+
+#[test]
+fn invalid_operands_are_reported() {
+    assert_eq!(error("v0 := vg", Syntax::Octo).to_string(), "Line 1: Expected a number, found vg");
+    assert_eq!(error("LD VX, 1", Syntax::Cowgod).to_string(), "Line 1: Expected a register, found VX");
+    assert_eq!(error("LD V0, #123", Syntax::Cowgod).to_string(), "Line 1: 291 doesn't fit in a byte");
+    assert_eq!(error("sprite v0 v1 16", Syntax::Octo).to_string(), "Line 1: 16 doesn't fit in 4 bits");
+    assert_eq!(error("jump 0x1000", Syntax::Octo).to_string(), "Line 1: 0x1000 doesn't fit in a 12-bit address");
+    assert_eq!(error("v0 +=", Syntax::Octo).to_string(), "Line 1: Unexpected end of file");
+}
+
+#[test]
+fn programs_must_fit_in_memory() {
+    // The largest program fills XO-CHIP's memory from 0x200 up to 0xFFFF
+    let largest = "0\n".repeat(0x10000 - 0x200);
+    assert_eq!(assemble(&largest, Syntax::Octo).unwrap().rom.len(), 0x10000 - 0x200);
+    let too_large = format!("{}0", largest);
+    assert_eq!(error(&too_large, Syntax::Octo), AssemblyError {
+        line: 0x10000 - 0x200 + 1,
+        message: "The program is 65025 bytes long, which doesn't fit in memory".to_string()
+    });
+
+    // Past 0xFFF, labels can only be reached with i := long
+    let far = format!("jump far\n{}: far\nclear", "0\n".repeat(0xE00));
+    assert_eq!(error(&far, Syntax::Octo).to_string(), "Line 1: Label far at 0x1002 is out of reach of a 12-bit address");
+    let far = format!("i := long far\n{}: far\nclear", "0\n".repeat(0xE00));
+    assert!(assemble(&far, Syntax::Octo).is_ok());
+}
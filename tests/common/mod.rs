@@ -0,0 +1,15 @@
+// Helpers shared by the integration tests
+
+use rust_chip8_emulator::assembler::assemble;
+use rust_chip8_emulator::chip8::Chip8;
+use rust_chip8_emulator::disassembler::Syntax;
+use rust_chip8_emulator::quirks::Quirks;
+use rust_chip8_emulator::variant::Variant;
+
+// A machine with the Octo source loaded, using the default quirks of the variant
+pub fn machine(source: &str, variant: Variant, seed: u64) -> Chip8 {
+    let program = assemble(source, Syntax::Octo).unwrap();
+    let mut chip8 = Chip8::new(variant, Quirks::for_variant(&variant), seed);
+    chip8.load_rom(&program.rom).unwrap();
+    chip8
+}
@@ -0,0 +1,100 @@
+// Runs the test programs in tests/roms through the machine and compares the final
+// framebuffer with the golden one in tests/golden, for each variant it applies to.
+// The programs are Octo sources assembled on the fly; their comments describe what
+// the golden framebuffers should show. They were written for this emulator, which
+// produced the golden framebuffers, so they only catch regressions (see the readme).
+//
+// After an intended change in behaviour, regenerate the golden framebuffers with
+//     UPDATE_GOLDEN=1 cargo test --no-default-features --test conformance
+// and check the differences before committing them.
+
+mod common;
+
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+use rust_chip8_emulator::dump::framebuffer_to_ascii;
+use rust_chip8_emulator::variant::Variant;
+use common::machine;
+
+const ALL_VARIANTS: [Variant; 4] = [Variant::CosmacVip, Variant::SuperChip, Variant::SuperChipExtended, Variant::XoChip];
+const SUPER_CHIP_VARIANTS: [Variant; 3] = [Variant::SuperChip, Variant::SuperChipExtended, Variant::XoChip];
+
+// Each key press is a key and the frames it is held during
+fn check(rom: &str, variants: &[Variant], frames: u32, presses: &[(usize, Range<u32>)]) {
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let source = fs::read_to_string(directory.join("roms").join(format!("{}.8o", rom))).unwrap();
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut failures = Vec::new();
+    for variant in variants {
+        let mut chip8 = machine(&source, *variant, 0);
+        for frame in 0..frames {
+            for (key, held) in presses {
+                chip8.set_key(*key, held.contains(&frame));
+            }
+            if let Err(error) = chip8.run_frame() {
+                panic!("{} on {}: {}", rom, variant, error);
+            }
+        }
+        let framebuffer = framebuffer_to_ascii(chip8.framebuffer());
+
+        let golden_path = directory.join("golden").join(format!("{}.{}.txt", rom, variant));
+        if update {
+            fs::write(&golden_path, &framebuffer).unwrap();
+            continue;
+        }
+        let Ok(golden) = fs::read_to_string(&golden_path) else {
+            failures.push(format!("{} is missing, run with UPDATE_GOLDEN=1 to create it", golden_path.display()));
+            continue;
+        };
+        if framebuffer != golden {
+            let row = framebuffer.lines().zip(golden.lines()).position(|(actual, expected)| actual != expected).unwrap_or(0);
+            failures.push(format!(
+                "{} on {} differs from {} from row {}, got:\n{}",
+                rom, variant, golden_path.display(), row, framebuffer
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn logo() {
+    check("logo", &ALL_VARIANTS, 30, &[]);
+}
+
+#[test]
+fn font() {
+    check("font", &ALL_VARIANTS, 60, &[]);
+}
+
+#[test]
+fn flags() {
+    check("flags", &ALL_VARIANTS, 120, &[]);
+}
+
+#[test]
+fn quirks() {
+    check("quirks", &ALL_VARIANTS, 120, &[]);
+}
+
+#[test]
+fn keypad() {
+    check("keypad", &ALL_VARIANTS, 40, &[(0xA, 5..8), (0x5, 20..25)]);
+}
+
+#[test]
+fn scroll() {
+    check("scroll", &SUPER_CHIP_VARIANTS, 30, &[]);
+}
+
+#[test]
+fn hires() {
+    check("hires", &SUPER_CHIP_VARIANTS, 30, &[]);
+}
+
+#[test]
+fn planes() {
+    check("planes", &[Variant::XoChip], 30, &[]);
+}
@@ -0,0 +1,121 @@
+// Disassembles small programs and checks the listings in both syntaxes: the labels,
+// code told apart from data by following the control flow, and XO-CHIP's 4 bytes long
+// F000 NNNN.
+
+use rust_chip8_emulator::disassembler::{disassemble, Syntax};
+use rust_chip8_emulator::variant::Variant;
+
+// Calls a subroutine drawing a sprite, then loops forever. The bytes after the loop
+// and the sprite can't be reached, so they are data.
+const PROGRAM: [u8; 17] = [
+    0x22, 0x06, // :call draw
+    0x12, 0x02, // : loop jump loop
+    0xF0, 0x90, // never reached
+    0xA2, 0x0C, // : draw i := sprite
+    0xD0, 0x15, // sprite v0 v1 5
+    0x00, 0xEE, // return
+    0xF0, 0x90, 0x90, 0x90, 0xF0 // : sprite
+];
+
+#[test]
+fn octo_listing() {
+    assert_eq!(disassemble(&PROGRAM, Variant::CosmacVip, Syntax::Octo), "\
+0200  22 06        :call sub_0206
+: label_0202
+0202  12 02        jump label_0202
+0204  F0 90        0xF0 0x90
+: sub_0206
+0206  A2 0C        i := data_020C
+0208  D0 15        sprite v0 v1 5
+020A  00 EE        return
+: data_020C
+020C  F0 90 90 90  0xF0 0x90 0x90 0x90
+0210  F0           0xF0
+");
+}
+
+#[test]
+fn cowgod_listing() {
+    assert_eq!(disassemble(&PROGRAM, Variant::CosmacVip, Syntax::Cowgod), "\
+0200  22 06        CALL sub_0206
+label_0202:
+0202  12 02        JP label_0202
+0204  F0 90        DB #F0, #90
+sub_0206:
+0206  A2 0C        LD I, data_020C
+0208  D0 15        DRW V0, V1, 5
+020A  00 EE        RET
+data_020C:
+020C  F0 90 90 90  DB #F0, #90, #90, #90
+0210  F0           DB #F0
+");
+}
+
+#[test]
+fn skipped_instructions_are_code_and_computed_jumps_are_not_followed() {
+    let rom = [
+        0x30, 0x01, // if v0 != 1 then
+        0x12, 0x06, // jump 0x206
+        0x00, 0xE0, // only reached by skipping the jump
+        0xB2, 0x0A, // jump0 0x20A
+        0x12, 0x34, 0x56, 0x78 // where BNNN may lead, unknown
+    ];
+    assert_eq!(disassemble(&rom, Variant::CosmacVip, Syntax::Octo), "\
+0200  30 01        if v0 != 0x01 then
+0202  12 06        jump label_0206
+0204  00 E0        clear
+: label_0206
+0206  B2 0A        jump0 0x20A # computed jump, not followed
+0208  12 34 56 78  0x12 0x34 0x56 0x78
+");
+}
+
+#[test]
+fn computed_jumps_add_vx_with_the_jumping_quirk() {
+    let rom = [0xB3, 0x0A]; // jump to 0x30A + v3 on SUPER-CHIP
+    assert_eq!(disassemble(&rom, Variant::SuperChip, Syntax::Octo), "\
+0200  B3 0A        jump0 0x30A # read as BXNN, jumps to 0x30A + v3, not followed
+");
+    assert_eq!(disassemble(&rom, Variant::SuperChip, Syntax::Cowgod), "\
+0200  B3 0A        JP V3, #0A ; computed jump, not followed
+");
+    assert_eq!(disassemble(&rom, Variant::CosmacVip, Syntax::Cowgod), "\
+0200  B3 0A        JP V0, #30A ; computed jump, not followed
+");
+}
+
+#[test]
+fn long_load_takes_4_bytes_on_xo_chip() {
+    let rom = [
+        0xF0, 0x00, 0x02, 0x0C, // i := long data
+        0x30, 0x00, // if v0 != 0 then, skipping all 4 bytes of the next instruction
+        0xF0, 0x00, 0x02, 0x0C, // i := long data
+        0x12, 0x0A, // : loop jump loop
+        0x01, 0x02 // : data
+    ];
+    assert_eq!(disassemble(&rom, Variant::XoChip, Syntax::Octo), "\
+0200  F0 00 02 0C  i := long data_020C
+0204  30 00        if v0 != 0x00 then
+0206  F0 00 02 0C  i := long data_020C
+: label_020A
+020A  12 0A        jump label_020A
+: data_020C
+020C  01 02        0x01 0x02
+");
+    assert_eq!(disassemble(&rom, Variant::XoChip, Syntax::Cowgod), "\
+0200  F0 00 02 0C  LD I, LONG data_020C
+0204  30 00        SE V0, #00
+0206  F0 00 02 0C  LD I, LONG data_020C
+label_020A:
+020A  12 0A        JP label_020A
+data_020C:
+020C  01 02        DB #01, #02
+");
+    // F000 is unknown before XO-CHIP, so nothing can be traced from it
+    assert_eq!(disassemble(&rom, Variant::SuperChip, Syntax::Octo), "\
+0200  F0 00 02 0C  0xF0 0x00 0x02 0x0C
+0204  30 00 F0 00  0x30 0x00 0xF0 0x00
+0208  02 0C 12 0A  0x02 0x0C 0x12 0x0A
+020C  01 02        0x01 0x02
+");
+}
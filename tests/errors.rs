@@ -0,0 +1,51 @@
+// Runs programs off the end of memory and checks that the machine halts with an error
+// instead of panicking, in particular at the end of XO-CHIP's 64 KiB where the program
+// counter can't go any further.
+
+use rust_chip8_emulator::chip8::{Chip8, PROGRAM_START};
+use rust_chip8_emulator::error::EmulatorError;
+use rust_chip8_emulator::quirks::Quirks;
+use rust_chip8_emulator::variant::Variant;
+
+// A program filling the whole memory with 0000, which is ignored, ending with `end`
+fn machine(variant: Variant, end: &[u8]) -> Chip8 {
+    let mut rom = vec![0; variant.memory_size() - PROGRAM_START];
+    let start = rom.len() - end.len();
+    rom[start..].copy_from_slice(end);
+    let mut chip8 = Chip8::new(variant, Quirks::for_variant(&variant), 0);
+    chip8.load_rom(&rom).unwrap();
+    chip8
+}
+
+fn run_until_error(chip8: &mut Chip8) -> EmulatorError {
+    for _ in 0..0x10000 {
+        if let Err(error) = chip8.step() {
+            return error;
+        }
+    }
+    panic!("The program didn't stop");
+}
+
+#[test]
+fn running_off_the_end_of_memory_halts() {
+    let error = run_until_error(&mut machine(Variant::CosmacVip, &[]));
+    assert!(matches!(error, EmulatorError::ProgramCounterOutOfBounds { pc: 0x1000 }), "{}", error);
+
+    // The program counter would wrap around to 0 after the last instruction
+    let error = run_until_error(&mut machine(Variant::XoChip, &[]));
+    assert!(matches!(error, EmulatorError::ProgramCounterOutOfBounds { pc: 0xFFFE }), "{}", error);
+}
+
+#[test]
+fn skipping_the_last_long_instruction_halts() {
+    // 0xFFFA: skip if V0 == 0, over the F000 NNNN at 0xFFFC
+    let error = run_until_error(&mut machine(Variant::XoChip, &[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34]));
+    assert!(matches!(error, EmulatorError::ProgramCounterOutOfBounds { pc: 0xFFFA }), "{}", error);
+}
+
+#[test]
+fn long_load_at_the_end_of_memory_halts() {
+    // 0xFFFC: F000 NNNN, with NNNN in the last two bytes of memory
+    let error = run_until_error(&mut machine(Variant::XoChip, &[0xF0, 0x00, 0x12, 0x34]));
+    assert!(matches!(error, EmulatorError::ProgramCounterOutOfBounds { pc: 0xFFFC }), "{}", error);
+}
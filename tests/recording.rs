@@ -0,0 +1,71 @@
+// Records the sound of small programs to WAV in memory and checks that it follows the
+// emulated frames: 1/60th of a second of audio per frame, playing exactly while the
+// sound timer runs.
+
+mod common;
+
+use std::io::Cursor;
+use rust_chip8_emulator::audio::AudioSettings;
+use rust_chip8_emulator::variant::Variant;
+use rust_chip8_emulator::wav::{WavRecorder, RECORDING_SAMPLE_RATE};
+use common::machine;
+
+// Runs the program for a number of frames and returns the recorded samples
+fn record(source: &str, variant: Variant, frames: u32) -> Vec<i16> {
+    let mut chip8 = machine(source, variant, 0);
+    let mut recorder = WavRecorder::new(Cursor::new(Vec::new()), &AudioSettings::default()).unwrap();
+    for _ in 0..frames {
+        chip8.run_frame().unwrap();
+        recorder.record_frame(&chip8.sound()).unwrap();
+    }
+    let wav = recorder.finish().unwrap().into_inner();
+
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()) as usize, wav.len() - 8);
+    assert_eq!(&wav[36..40], b"data");
+    assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize, wav.len() - 44);
+    wav[44..].chunks(2).map(|sample| i16::from_le_bytes([sample[0], sample[1]])).collect()
+}
+
+// The loudest sample of each frame
+fn frame_peaks(samples: &[i16]) -> Vec<i16> {
+    let samples_per_frame = RECORDING_SAMPLE_RATE as usize / 60;
+    samples.chunks(samples_per_frame).map(|frame| frame.iter().map(|sample| sample.saturating_abs()).max().unwrap()).collect()
+}
+
+#[test]
+fn beep_lasts_as_long_as_the_sound_timer() {
+    let samples = record(": main v0 := 30 buzzer := v0 : loop jump loop", Variant::CosmacVip, 60);
+    assert_eq!(samples.len(), RECORDING_SAMPLE_RATE as usize);
+
+    // The timer is set then ticks once during the first frame, so it sounds for 29 frames
+    // and fades out during the next one
+    let peaks = frame_peaks(&samples);
+    assert!(peaks[..29].iter().all(|peak| *peak > 4000), "{:?}", peaks);
+    assert!(peaks[31..].iter().all(|peak| *peak == 0), "{:?}", peaks);
+}
+
+#[test]
+fn frames_add_up_to_whole_seconds() {
+    let samples = record(": main : loop jump loop", Variant::CosmacVip, 600);
+    assert_eq!(samples.len(), 10 * RECORDING_SAMPLE_RATE as usize);
+    assert!(samples.iter().all(|sample| *sample == 0));
+}
+
+#[test]
+fn xo_chip_pattern_replaces_the_beep() {
+    // A pattern of 8 high bits followed by 8 low bits, at the default 4000 bits per second
+    let source = ": main
+        i := pattern
+        audio
+        v0 := 60
+        buzzer := v0
+        : loop jump loop
+        : pattern
+        0xFF 0x00 0xFF 0x00 0xFF 0x00 0xFF 0x00 0xFF 0x00 0xFF 0x00 0xFF 0x00 0xFF 0x00";
+    let samples = record(source, Variant::XoChip, 60);
+    let second_half = &samples[samples.len() / 2..];
+    let crossings = second_half.windows(2).filter(|pair| (pair[0] < 0) != (pair[1] < 0)).count();
+    // 250Hz during half a second: 125 periods, each crossing zero twice
+    assert!((245..=255).contains(&crossings), "{} zero crossings", crossings);
+}
@@ -0,0 +1,64 @@
+// Records every frame of a running program and checks that rewinding goes back through
+// the very same states, and that a minute of frames stays small.
+
+mod common;
+
+use rust_chip8_emulator::rewind::{Rewind, DEFAULT_REWIND_FRAMES};
+use rust_chip8_emulator::variant::Variant;
+use common::machine;
+
+// Keeps drawing random digits and counting in memory
+const SOURCE: &str = ": main
+    clear
+    : loop
+    v0 := random 0x3F
+    v1 := random 0x1F
+    v3 := random 0x0F
+    i := hex v3
+    sprite v0 v1 5
+    i := counter
+    load v0
+    v0 += 1
+    save v0
+    jump loop
+    : counter
+    0";
+
+#[test]
+fn rewinding_goes_back_through_the_same_states() {
+    let mut chip8 = machine(SOURCE, Variant::SuperChip, 0);
+    let mut rewind = Rewind::new(DEFAULT_REWIND_FRAMES);
+    let mut states = vec![chip8.save_state()];
+    rewind.push(chip8.save_state());
+    for _ in 0..300 {
+        chip8.run_frame().unwrap();
+        states.push(chip8.save_state());
+        rewind.push(chip8.save_state());
+    }
+    states.pop();
+
+    assert_eq!(rewind.len(), 300);
+    while let Some(state) = rewind.step_back() {
+        assert_eq!(state, states.pop().unwrap());
+        chip8.load_state(&state).unwrap();
+    }
+    assert!(states.is_empty());
+    assert!(rewind.is_empty());
+}
+
+#[test]
+fn keeps_a_minute_of_frames() {
+    for variant in [Variant::CosmacVip, Variant::XoChip] {
+        let mut chip8 = machine(SOURCE, variant, 0);
+        let mut rewind = Rewind::new(DEFAULT_REWIND_FRAMES);
+        rewind.push(chip8.save_state());
+        for _ in 0..DEFAULT_REWIND_FRAMES + 100 {
+            chip8.run_frame().unwrap();
+            rewind.push(chip8.save_state());
+        }
+        assert_eq!(rewind.len(), DEFAULT_REWIND_FRAMES);
+        // Far less than the whole state every frame, which would take 12 KiB a frame
+        // (74 KiB on XO-CHIP)
+        assert!(rewind.size_in_bytes() < 200 * DEFAULT_REWIND_FRAMES, "{} bytes on {}", rewind.size_in_bytes(), variant);
+    }
+}
@@ -0,0 +1,39 @@
+// Checks that FX75 leaves the RPL flags to the caller to save instead of writing them
+// to disk itself, and that FX85 reads back the flags the caller restored.
+
+mod common;
+
+use rust_chip8_emulator::variant::Variant;
+use common::machine;
+
+#[test]
+fn saved_flags_are_handed_to_the_caller_once() {
+    let mut chip8 = machine(": main
+        v0 := 1
+        v1 := 2
+        saveflags v1
+        : loop
+        jump loop", Variant::SuperChip, 0);
+    assert_eq!(chip8.take_dirty_flags(), None);
+
+    chip8.run_frame().unwrap();
+    let flags = chip8.take_dirty_flags().unwrap();
+    assert_eq!(flags[..3], [1, 2, 0]);
+    assert_eq!(chip8.take_dirty_flags(), None);
+    assert_eq!(chip8.rpl_flags(), &flags);
+}
+
+#[test]
+fn restored_flags_are_loaded_by_the_program() {
+    let mut chip8 = machine(": main
+        loadflags v2
+        : loop
+        jump loop", Variant::SuperChip, 0);
+    let mut flags = [0; 16];
+    flags[..3].copy_from_slice(&[7, 8, 9]);
+    chip8.set_rpl_flags(flags);
+
+    chip8.run_frame().unwrap();
+    assert_eq!(chip8.registers()[..3], [7, 8, 9]);
+    assert_eq!(chip8.take_dirty_flags(), None);
+}
@@ -0,0 +1,91 @@
+// Saves the state of a running program, restores it into another machine and checks
+// that both carry on identically, then that save states which don't fit are refused.
+
+mod common;
+
+use rust_chip8_emulator::chip8::Chip8;
+use rust_chip8_emulator::dump::{framebuffer_to_ascii, registers_to_json};
+use rust_chip8_emulator::savestate::SaveStateError;
+use rust_chip8_emulator::variant::Variant;
+use common::machine;
+
+// Draws digits at random places with a random delay, calling a subroutine so that the
+// stack is in use, and beeps now and then
+const SOURCE: &str = ": main
+    clear
+    : loop
+    draw-random
+    v2 := random 0x0F
+    delay := v2
+    buzzer := v2
+    : wait
+    v2 := delay
+    if v2 != 0 then jump wait
+    jump loop
+    : draw-random
+    v0 := random 0x3F
+    v1 := random 0x1F
+    v3 := random 0x0F
+    i := hex v3
+    sprite v0 v1 5
+    return";
+
+fn run_frames(chip8: &mut Chip8, frames: u32) {
+    for _ in 0..frames {
+        chip8.run_frame().unwrap();
+    }
+}
+
+#[test]
+fn restored_machine_carries_on_identically() {
+    for variant in [Variant::CosmacVip, Variant::SuperChip, Variant::XoChip] {
+        let mut original = machine(SOURCE, variant, 1);
+        run_frames(&mut original, 100);
+        let state = original.save_state();
+
+        // Another seed, so that only the saved random number generator gives the same numbers
+        let mut restored = machine(SOURCE, variant, 2);
+        run_frames(&mut restored, 37);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+
+        run_frames(&mut original, 200);
+        run_frames(&mut restored, 200);
+        assert_eq!(framebuffer_to_ascii(restored.framebuffer()), framebuffer_to_ascii(original.framebuffer()));
+        assert_eq!(registers_to_json(&restored), registers_to_json(&original));
+    }
+}
+
+#[test]
+fn other_program_is_refused() {
+    let mut chip8 = machine(SOURCE, Variant::CosmacVip, 1);
+    let state = machine(": main jump main", Variant::CosmacVip, 1).save_state();
+    assert!(matches!(chip8.load_state(&state), Err(SaveStateError::WrongProgram { .. })));
+}
+
+#[test]
+fn other_variant_is_refused() {
+    let mut chip8 = machine(SOURCE, Variant::CosmacVip, 1);
+    let state = machine(SOURCE, Variant::SuperChip, 1).save_state();
+    assert_eq!(chip8.load_state(&state), Err(SaveStateError::WrongVariant));
+}
+
+#[test]
+fn damaged_save_states_leave_the_machine_alone() {
+    let mut chip8 = machine(SOURCE, Variant::CosmacVip, 1);
+    run_frames(&mut chip8, 10);
+    let state = chip8.save_state();
+    run_frames(&mut chip8, 10);
+    let before = chip8.save_state();
+
+    assert_eq!(chip8.load_state(b"not a save state"), Err(SaveStateError::NotASaveState));
+    let mut newer = state.clone();
+    newer[4] += 1;
+    assert_eq!(chip8.load_state(&newer), Err(SaveStateError::UnsupportedVersion { version: newer[4] }));
+    assert_eq!(chip8.load_state(&state[..state.len() - 1]), Err(SaveStateError::Corrupted));
+    let mut longer = state.clone();
+    longer.push(0);
+    assert_eq!(chip8.load_state(&longer), Err(SaveStateError::Corrupted));
+
+    assert_eq!(chip8.save_state(), before);
+}
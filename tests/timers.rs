@@ -0,0 +1,86 @@
+// Checks that the timers count the executed cycles, whether the machine is driven one
+// instruction at a time with `step` or a frame at a time with `run_frame`.
+
+mod common;
+
+use rust_chip8_emulator::variant::Variant;
+use common::machine;
+
+// Sets both timers to 60 and waits for the delay timer to run out
+const SOURCE: &str = ": main
+    v0 := 60
+    delay := v0
+    buzzer := v0
+    : loop
+    v0 := delay
+    if v0 != 0 then jump loop
+    : end
+    jump end";
+
+#[test]
+fn stepping_ticks_the_timers_every_frame() {
+    let mut chip8 = machine(SOURCE, Variant::SuperChip, 0);
+    // The timers are set on the third cycle, and tick for the first time on the tenth
+    for _ in 0..9 {
+        chip8.step().unwrap();
+    }
+    assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (60, 60));
+    assert!(!chip8.at_frame_start());
+    chip8.step().unwrap();
+    assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (59, 59));
+    assert!(chip8.at_frame_start());
+
+    for _ in 0..10 * 59 {
+        chip8.step().unwrap();
+    }
+    assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (0, 0));
+}
+
+#[test]
+fn frame_runs_up_to_the_next_tick() {
+    let mut stepped = machine(SOURCE, Variant::SuperChip, 0);
+    let mut framed = machine(SOURCE, Variant::SuperChip, 0);
+    for _ in 0..4 {
+        stepped.step().unwrap();
+        framed.step().unwrap();
+    }
+    // Only the 6 cycles left of the first frame run
+    framed.run_frame().unwrap();
+    for _ in 0..6 {
+        stepped.step().unwrap();
+    }
+    assert_eq!(framed.save_state(), stepped.save_state());
+
+    for _ in 0..30 {
+        framed.run_frame().unwrap();
+        for _ in 0..10 {
+            stepped.step().unwrap();
+        }
+    }
+    assert_eq!(framed.save_state(), stepped.save_state());
+    assert_eq!(framed.delay_timer(), 29);
+}
+
+#[test]
+fn display_wait_skips_to_the_next_frame() {
+    // Draws in a loop, each draw ending the frame it runs in
+    let mut chip8 = machine(": main
+        v0 := 60
+        delay := v0
+        : loop
+        sprite v1 v1 1
+        jump loop", Variant::CosmacVip, 0);
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+    assert_eq!(chip8.delay_timer(), 59);
+    assert!(chip8.at_frame_start());
+    let state = chip8.save_state();
+
+    // The next frame runs up to the next draw, not only the jump after the last one
+    chip8.run_frame().unwrap();
+    assert_eq!(chip8.delay_timer(), 58);
+    assert_eq!(chip8.program_counter(), 0x206);
+    chip8.load_state(&state).unwrap();
+    assert_eq!(chip8.save_state(), state);
+}